@@ -0,0 +1,78 @@
+//! Per-tick cost of `MonitorStats`, which only builds readers for enabled
+//! monitors, with the `sysinfo` refresh it used before reading through the
+//! root kept as a baseline
+//!
+//! Run with `cargo bench --bench sampling`.
+
+use cosmic_applet_systemstats::config::Config;
+use cosmic_applet_systemstats::monitors::cpu::CpuStats;
+use cosmic_applet_systemstats::monitors::memory::MemoryStats;
+use cosmic_applet_systemstats::monitors::MonitorStats;
+use cosmic_applet_systemstats::sysfs::Root;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::time::{Duration, Instant};
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind, RefreshKind, System};
+
+/// What `MonitorStats::update` does for CPU usage and RAM: read `/proc/stat` and `/proc/meminfo`
+fn procfs_readers(c: &mut Criterion) {
+    let root = Root::new("/");
+    let mut cpu = CpuStats::new(&root);
+    let mut memory = MemoryStats::new(&root);
+
+    c.bench_function("procfs readers", |b| {
+        b.iter(|| {
+            cpu.update();
+            memory.update();
+            black_box((cpu.usage(), memory.used_bytes(), memory.total_bytes()))
+        })
+    });
+}
+
+/// Baseline: the same readings through `sysinfo`, one `refresh_specifics` call
+/// limited to CPU usage and RAM. It can't be pointed at a fixture tree.
+fn sysinfo_baseline(c: &mut Criterion) {
+    let refresh = RefreshKind::nothing()
+        .with_cpu(CpuRefreshKind::nothing().with_cpu_usage())
+        .with_memory(MemoryRefreshKind::nothing().with_ram());
+
+    let mut system = System::new();
+    system.refresh_specifics(refresh);
+
+    c.bench_function("sysinfo baseline", |b| {
+        b.iter(|| {
+            system.refresh_specifics(refresh);
+            black_box((system.global_cpu_usage(), system.used_memory(), system.total_memory()))
+        })
+    });
+}
+
+/// A tick of `MonitorStats` with `config`, stepping past every interval so each enabled monitor is due
+fn tick(c: &mut Criterion, name: &str, config: Config) {
+    let mut monitors = MonitorStats::new(&config, &Root::new("/"));
+    let mut now = Instant::now();
+
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            now += Duration::from_secs(60);
+            monitors.update(&config, now);
+            black_box(monitors.snapshot())
+        })
+    });
+}
+
+/// Every monitor, GPU sensors included
+fn full_tick(c: &mut Criterion) {
+    tick(c, "full tick", Config::default());
+}
+
+/// Only CPU usage and memory enabled: the other monitors have no reader to refresh
+fn cpu_and_memory_tick(c: &mut Criterion) {
+    let mut config = Config::default();
+    config.monitors.network = false;
+    config.monitors.cpu_temperature = false;
+    config.monitors.gpu_temperature = false;
+    tick(c, "cpu and memory tick", config);
+}
+
+criterion_group!(benches, procfs_readers, sysinfo_baseline, full_tick, cpu_and_memory_tick);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+//! Commands launched from panel clicks
+
+use std::env;
+use std::process::Command;
+use std::thread;
+
+/// Used when `$TERMINAL` isn't set
+const DEFAULT_TERMINAL: &str = "cosmic-term";
+
+/// Run `command` through the shell, optionally in a terminal, without waiting for it
+pub fn launch(command: &str, in_terminal: bool) {
+    let mut process = if in_terminal {
+        let terminal = env::var("TERMINAL").unwrap_or_else(|_| DEFAULT_TERMINAL.to_string());
+        let mut process = Command::new(terminal);
+        process.args(["-e", "sh", "-c", command]);
+        process
+    } else {
+        let mut process = Command::new("sh");
+        process.args(["-c", command]);
+        process
+    };
+
+    match process.spawn() {
+        Ok(mut child) => {
+            // Reap it when it exits so it doesn't linger as a zombie
+            let spawned = thread::Builder::new()
+                .name("click-command".into())
+                .spawn(move || child.wait());
+            if let Err(e) = spawned {
+                log::warn!("Failed to watch '{}': {}", command, e);
+            }
+        }
+        Err(e) => log::warn!("Failed to run '{}': {}", command, e),
+    }
+}
@@ -0,0 +1,348 @@
+//! Battery and session state, kept current from bus signals
+//!
+//! Both change rarely, so instead of polling each is read once and then
+//! again only when UPower or logind announce that a property changed.
+//! Without UPower the power supplies are re-read every [`POWER_POLL_INTERVAL`].
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use zbus::blocking::fdo::PropertiesProxy;
+use zbus::blocking::Connection;
+use zbus::zvariant::ObjectPath;
+
+use super::logind::{self, Login};
+use super::power::{self, Power};
+use crate::sysfs::Root;
+
+/// How often the power supplies are re-read while UPower can't be followed
+pub const POWER_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What outside the readings decides how fast to sample
+#[derive(Debug, Default)]
+pub struct Environment {
+    on_battery: AtomicBool,
+    inactive: AtomicBool,
+    /// Set while the battery comes from the power supplies rather than UPower
+    power_supplies: Mutex<Option<PowerSupplies>>,
+}
+
+#[derive(Debug)]
+struct PowerSupplies {
+    root: Root,
+    read_at: Instant,
+}
+
+impl Environment {
+    pub fn on_battery(&self) -> bool {
+        self.on_battery.load(Ordering::Relaxed)
+    }
+
+    /// The session is idle or its screen is locked
+    pub fn inactive(&self) -> bool {
+        self.inactive.load(Ordering::Relaxed)
+    }
+
+    pub fn set_on_battery(&self, on_battery: bool) {
+        self.on_battery.store(on_battery, Ordering::Relaxed);
+    }
+
+    pub fn set_inactive(&self, inactive: bool) {
+        self.inactive.store(inactive, Ordering::Relaxed);
+    }
+
+    /// Take the battery state from the power supplies under `root` from now on
+    pub fn read_power_supplies(&self, root: &Root) {
+        self.set_on_battery(power::on_battery(root));
+        if let Ok(mut supplies) = self.power_supplies.lock() {
+            *supplies = Some(PowerSupplies {
+                root: root.clone(),
+                read_at: Instant::now(),
+            });
+        }
+    }
+
+    /// Stop polling once UPower supplies the battery state
+    fn forget_power_supplies(&self) {
+        if let Ok(mut supplies) = self.power_supplies.lock() {
+            *supplies = None;
+        }
+    }
+
+    /// Re-read the power supplies if they're in use and were last read
+    /// [`POWER_POLL_INTERVAL`] before `now`; cheap to call every tick
+    pub fn poll_power_supplies(&self, now: Instant) {
+        let Ok(mut supplies) = self.power_supplies.lock() else {
+            return;
+        };
+        if let Some(supplies) = supplies.as_mut() {
+            if now.saturating_duration_since(supplies.read_at) >= POWER_POLL_INTERVAL {
+                self.set_on_battery(power::on_battery(&supplies.root));
+                supplies.read_at = now;
+            }
+        }
+    }
+}
+
+/// Start following the power source and session state
+///
+/// Without UPower the battery is read from the power supplies under `root`,
+/// for [`Environment::poll_power_supplies`] to refresh, and without logind the
+/// session stays active. A captured root is never mixed with the live
+/// system's bus.
+pub fn watch(root: &Root) -> Arc<Environment> {
+    let environment = Arc::new(Environment::default());
+    environment.read_power_supplies(root);
+
+    if !root.is_live() {
+        return environment;
+    }
+    match Connection::system() {
+        Ok(connection) => {
+            follow_power(&connection, root, environment.clone());
+            follow_session(&connection, environment.clone());
+        }
+        Err(e) => log::info!(
+            "Polling the battery from /sys, and the session state won't update: {}",
+            e
+        ),
+    }
+    environment
+}
+
+/// Keep `on_battery` in step with UPower on a background thread, falling
+/// back to the power supplies under `root` if it can't be reached or goes away
+pub fn follow_power(connection: &Connection, root: &Root, environment: Arc<Environment>) {
+    let connection = connection.clone();
+    let root = root.clone();
+    spawn("upower", move || {
+        let result = follow_upower(&connection, &environment);
+        environment.read_power_supplies(&root);
+        result
+    });
+}
+
+fn follow_upower(connection: &Connection, environment: &Environment) -> zbus::Result<()> {
+    let power = Power::connect(connection)?;
+    let path = ObjectPath::try_from(power::PATH)?;
+    on_change(connection, power::SERVICE, &path, || {
+        environment.set_on_battery(power.on_battery()?);
+        environment.forget_power_supplies();
+        Ok(())
+    })
+}
+
+/// Keep `inactive` in step with the logind session on a background thread
+pub fn follow_session(connection: &Connection, environment: Arc<Environment>) {
+    let connection = connection.clone();
+    spawn("logind", move || {
+        let login = Login::connect(&connection)?;
+        let result = on_change(&connection, logind::SERVICE, login.path(), || {
+            environment.set_inactive(login.is_inactive()?);
+            Ok(())
+        });
+        // Better sampled too often than stuck slow
+        environment.set_inactive(false);
+        result
+    });
+}
+
+/// Run `refresh` now and again whenever `service` announces a property change at `path`
+///
+/// Returns once the connection closes or `refresh` fails.
+fn on_change(
+    connection: &Connection,
+    service: &str,
+    path: &ObjectPath<'_>,
+    mut refresh: impl FnMut() -> zbus::Result<()>,
+) -> zbus::Result<()> {
+    let properties = PropertiesProxy::builder(connection)
+        .destination(service)?
+        .path(path)?
+        .build()?;
+    // Subscribe before the first read so a change in between isn't missed
+    let changes = properties.receive_properties_changed()?;
+
+    refresh()?;
+    for _ in changes {
+        refresh()?;
+    }
+    Ok(())
+}
+
+fn spawn(name: &'static str, run: impl FnOnce() -> zbus::Result<()> + Send + 'static) {
+    let spawned = thread::Builder::new().name(name.into()).spawn(move || {
+        if let Err(e) = run() {
+            log::info!("Stopped following {}: {}", name, e);
+        }
+    });
+    if let Err(e) = spawned {
+        log::warn!("Failed to start {} thread: {}", name, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bus::TestBus;
+    use std::time::{Duration, Instant};
+    use zbus::blocking::connection::Builder;
+    use zbus::zvariant::OwnedObjectPath;
+
+    const SESSION_PATH: &str = "/org/freedesktop/login1/session/_31";
+
+    struct StubUPower {
+        on_battery: bool,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.UPower")]
+    impl StubUPower {
+        #[zbus(property)]
+        fn on_battery(&self) -> bool {
+            self.on_battery
+        }
+    }
+
+    struct StubManager;
+
+    #[zbus::interface(name = "org.freedesktop.login1.Manager")]
+    impl StubManager {
+        fn get_session(&self, session_id: &str) -> zbus::fdo::Result<OwnedObjectPath> {
+            assert_eq!(session_id, "auto");
+            Ok(OwnedObjectPath::try_from(SESSION_PATH).unwrap())
+        }
+    }
+
+    struct StubSession {
+        idle: bool,
+        locked: bool,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.login1.Session")]
+    impl StubSession {
+        #[zbus(property)]
+        fn idle_hint(&self) -> bool {
+            self.idle
+        }
+
+        #[zbus(property)]
+        fn locked_hint(&self) -> bool {
+            self.locked
+        }
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            if Instant::now() > deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+
+    #[test]
+    fn battery_follows_upower_signals() {
+        let Some(bus) = TestBus::start() else {
+            return;
+        };
+        let server = Builder::address(bus.address.as_str())
+            .unwrap()
+            .name(power::SERVICE)
+            .unwrap()
+            .serve_at(power::PATH, StubUPower { on_battery: true })
+            .unwrap()
+            .build()
+            .unwrap();
+        let client = Builder::address(bus.address.as_str()).unwrap().build().unwrap();
+
+        let environment = Arc::new(Environment::default());
+        follow_power(&client, &Root::new("/nonexistent"), environment.clone());
+        assert!(wait_for(|| environment.on_battery()));
+
+        let upower = server.object_server().interface::<_, StubUPower>(power::PATH).unwrap();
+        upower.get_mut().on_battery = false;
+        zbus::block_on(upower.get().on_battery_changed(upower.signal_emitter())).unwrap();
+        assert!(wait_for(|| !environment.on_battery()));
+    }
+
+    /// A root with one system battery in `status`
+    fn battery_root(dir: &std::path::Path, status: &str) -> Root {
+        let root = Root::new(dir);
+        let battery = root.join("/sys/class/power_supply/BAT0");
+        std::fs::create_dir_all(&battery).unwrap();
+        std::fs::write(battery.join("type"), "Battery\n").unwrap();
+        std::fs::write(battery.join("status"), format!("{}\n", status)).unwrap();
+        root
+    }
+
+    #[test]
+    fn power_supplies_are_reread_every_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = battery_root(dir.path(), "Discharging");
+        let environment = Environment::default();
+        environment.read_power_supplies(&root);
+        assert!(environment.on_battery());
+
+        battery_root(dir.path(), "Charging");
+        let start = Instant::now();
+        environment.poll_power_supplies(start);
+        assert!(environment.on_battery());
+
+        environment.poll_power_supplies(start + POWER_POLL_INTERVAL);
+        assert!(!environment.on_battery());
+    }
+
+    #[test]
+    fn battery_falls_back_to_the_power_supplies_without_upower() {
+        let Some(bus) = TestBus::start() else {
+            return;
+        };
+        let client = Builder::address(bus.address.as_str()).unwrap().build().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let root = battery_root(dir.path(), "Discharging");
+
+        let environment = Arc::new(Environment::default());
+        follow_power(&client, &root, environment.clone());
+        assert!(wait_for(|| environment.power_supplies.lock().unwrap().is_some()));
+        assert!(environment.on_battery());
+
+        battery_root(dir.path(), "Full");
+        environment.poll_power_supplies(Instant::now() + POWER_POLL_INTERVAL);
+        assert!(!environment.on_battery());
+    }
+
+    #[test]
+    fn inactivity_follows_the_logind_session() {
+        let Some(bus) = TestBus::start() else {
+            return;
+        };
+        let server = Builder::address(bus.address.as_str())
+            .unwrap()
+            .name(logind::SERVICE)
+            .unwrap()
+            .serve_at("/org/freedesktop/login1", StubManager)
+            .unwrap()
+            .serve_at(SESSION_PATH, StubSession { idle: false, locked: true })
+            .unwrap()
+            .build()
+            .unwrap();
+        let client = Builder::address(bus.address.as_str()).unwrap().build().unwrap();
+
+        let environment = Arc::new(Environment::default());
+        follow_session(&client, environment.clone());
+        // The first read happens once subscribed, so no signal after this is missed
+        assert!(wait_for(|| environment.inactive()));
+
+        let session = server.object_server().interface::<_, StubSession>(SESSION_PATH).unwrap();
+        session.get_mut().locked = false;
+        zbus::block_on(session.get().locked_hint_changed(session.signal_emitter())).unwrap();
+        assert!(wait_for(|| !environment.inactive()));
+
+        session.get_mut().idle = true;
+        zbus::block_on(session.get().idle_hint_changed(session.signal_emitter())).unwrap();
+        assert!(wait_for(|| environment.inactive()));
+    }
+}
@@ -0,0 +1,57 @@
+//! Session idle and lock state from systemd-logind
+
+use zbus::blocking::Connection;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+
+pub const SERVICE: &str = "org.freedesktop.login1";
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn get_session(&self, session_id: &str) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/session/auto"
+)]
+trait Session {
+    #[zbus(property)]
+    fn idle_hint(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+}
+
+/// The session the applet runs in
+pub struct Login {
+    session: SessionProxyBlocking<'static>,
+}
+
+impl Login {
+    pub fn connect(connection: &Connection) -> zbus::Result<Self> {
+        // Changes are only announced on the session's own path, never on `session/auto`
+        let path = ManagerProxyBlocking::new(connection)?.get_session("auto")?;
+        // Only read after a change is announced, so a cache would just duplicate the signal
+        let session = SessionProxyBlocking::builder(connection)
+            .path(path)?
+            .cache_properties(CacheProperties::No)
+            .build()?;
+
+        Ok(Self { session })
+    }
+
+    pub fn path(&self) -> &ObjectPath<'_> {
+        self.session.inner().path()
+    }
+
+    /// Whether nobody is looking: the session is idle or its screen is locked
+    pub fn is_inactive(&self) -> zbus::Result<bool> {
+        Ok(self.session.idle_hint()? || self.session.locked_hint()?)
+    }
+}
@@ -0,0 +1,204 @@
+//! Adaptive refresh
+//!
+//! Stretches every sampling interval while fast updates would be wasted: on
+//! battery, while the session is idle or locked, and while readings hold
+//! steady. The first noticeable change drops straight back to full speed.
+
+pub mod environment;
+pub mod logind;
+pub mod power;
+
+use std::sync::Arc;
+
+use crate::config::Adaptive;
+use crate::monitors::Snapshot;
+use environment::Environment;
+
+/// Consecutive steady samples before the stable factor doubles
+const STABLE_SAMPLES: u32 = 5;
+
+/// Differences below these count as steady
+const CPU_USAGE_STEADY: f32 = 5.0; // Percentage points
+const TEMPERATURE_STEADY: f32 = 2.0; // Celsius
+const MEMORY_STEADY: f32 = 1.0; // Percentage points
+const NETWORK_STEADY: f64 = 0.1; // Fraction of the previous rate
+const NETWORK_NOISE_BPS: u64 = 1_000; // Background chatter below this is ignored
+
+/// Current slowdown and what it's based on
+pub struct Pace {
+    previous: Option<Snapshot>,
+    steady_samples: u32,
+    stable_factor: u32,
+    environment: Arc<Environment>,
+}
+
+impl Pace {
+    pub fn new(environment: Arc<Environment>) -> Self {
+        Self {
+            previous: None,
+            steady_samples: 0,
+            stable_factor: 1,
+            environment,
+        }
+    }
+
+    /// Take in a fresh snapshot and return the multiplier for every interval
+    pub fn observe(&mut self, config: &Adaptive, snapshot: &Snapshot) -> u32 {
+        if !config.enabled {
+            return 1;
+        }
+
+        let steady = self.previous.as_ref().is_some_and(|previous| is_steady(previous, snapshot));
+        self.previous = Some(snapshot.clone());
+
+        if steady {
+            self.steady_samples += 1;
+            if self.steady_samples >= STABLE_SAMPLES {
+                self.steady_samples = 0;
+                self.stable_factor = (self.stable_factor * 2).min(config.when_stable.max(1));
+            }
+        } else {
+            self.steady_samples = 0;
+            self.stable_factor = 1;
+        }
+
+        let mut factor = self.stable_factor;
+        if self.environment.on_battery() {
+            factor = factor.max(config.on_battery);
+        }
+        if self.environment.inactive() {
+            factor = factor.max(config.when_idle);
+        }
+        factor.max(1)
+    }
+}
+
+/// Whether nothing shown in the panel moved noticeably between two snapshots
+fn is_steady(previous: &Snapshot, next: &Snapshot) -> bool {
+    let temperature_steady = |a: Option<f32>, b: Option<f32>| match (a, b) {
+        (Some(a), Some(b)) => (a - b).abs() < TEMPERATURE_STEADY,
+        (a, b) => a.is_none() && b.is_none(),
+    };
+    let network_steady = |a: u64, b: u64| {
+        let difference = a.abs_diff(b);
+        difference < NETWORK_NOISE_BPS || difference as f64 <= a as f64 * NETWORK_STEADY
+    };
+
+    (previous.cpu_usage - next.cpu_usage).abs() < CPU_USAGE_STEADY
+        && temperature_steady(previous.cpu_celsius, next.cpu_celsius)
+        && temperature_steady(previous.gpu_celsius, next.gpu_celsius)
+        && (previous.memory_usage_percent() - next.memory_usage_percent()).abs() < MEMORY_STEADY
+        && network_steady(previous.download_bps, next.download_bps)
+        && network_steady(previous.upload_bps, next.upload_bps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Adaptive {
+        Adaptive {
+            enabled: true,
+            on_battery: 2,
+            when_idle: 4,
+            when_stable: 8,
+        }
+    }
+
+    fn cpu(usage: f32) -> Snapshot {
+        Snapshot {
+            cpu_usage: usage,
+            ..Snapshot::default()
+        }
+    }
+
+    /// Feed `count` copies of `snapshot`, returning the last factor
+    fn observe_steady(pace: &mut Pace, snapshot: &Snapshot, count: u32) -> u32 {
+        (0..count).map(|_| pace.observe(&config(), snapshot)).last().unwrap()
+    }
+
+    #[test]
+    fn steady_readings_step_up_to_the_stable_factor() {
+        let mut pace = Pace::new(Arc::default());
+
+        assert_eq!(observe_steady(&mut pace, &cpu(10.0), STABLE_SAMPLES), 1);
+        assert_eq!(pace.observe(&config(), &cpu(10.0)), 2);
+        assert_eq!(observe_steady(&mut pace, &cpu(10.0), STABLE_SAMPLES), 4);
+        assert_eq!(observe_steady(&mut pace, &cpu(10.0), STABLE_SAMPLES), 8);
+        assert_eq!(observe_steady(&mut pace, &cpu(10.0), 4 * STABLE_SAMPLES), 8);
+    }
+
+    #[test]
+    fn first_noticeable_change_steps_straight_down() {
+        let mut pace = Pace::new(Arc::default());
+        observe_steady(&mut pace, &cpu(10.0), 3 * STABLE_SAMPLES + 1);
+        assert_eq!(pace.observe(&config(), &cpu(10.0)), 8);
+
+        assert_eq!(pace.observe(&config(), &cpu(10.0 + CPU_USAGE_STEADY)), 1);
+    }
+
+    #[test]
+    fn small_changes_keep_the_stable_factor_but_restart_the_count() {
+        let mut pace = Pace::new(Arc::default());
+        assert_eq!(observe_steady(&mut pace, &cpu(10.0), STABLE_SAMPLES + 1), 2);
+
+        // Drifting below the threshold counts as steady
+        let drift = [12.0, 14.0, 16.0, 18.0];
+        for usage in drift {
+            assert_eq!(pace.observe(&config(), &cpu(usage)), 2);
+        }
+
+        // A jump resets everything, and a full run is needed to step up again
+        assert_eq!(pace.observe(&config(), &cpu(60.0)), 1);
+        assert_eq!(observe_steady(&mut pace, &cpu(60.0), STABLE_SAMPLES - 1), 1);
+        assert_eq!(pace.observe(&config(), &cpu(60.0)), 2);
+    }
+
+    #[test]
+    fn battery_and_idle_set_a_floor() {
+        let environment = Arc::new(Environment::default());
+        let mut pace = Pace::new(environment.clone());
+        assert_eq!(pace.observe(&config(), &cpu(10.0)), 1);
+
+        environment.set_on_battery(true);
+        assert_eq!(pace.observe(&config(), &cpu(50.0)), 2);
+
+        environment.set_inactive(true);
+        assert_eq!(pace.observe(&config(), &cpu(90.0)), 4);
+
+        environment.set_on_battery(false);
+        environment.set_inactive(false);
+        assert_eq!(pace.observe(&config(), &cpu(10.0)), 1);
+    }
+
+    #[test]
+    fn disabled_keeps_full_speed() {
+        let environment = Arc::new(Environment::default());
+        environment.set_on_battery(true);
+        let mut pace = Pace::new(environment);
+        let config = Adaptive { enabled: false, ..config() };
+
+        for _ in 0..3 * STABLE_SAMPLES {
+            assert_eq!(pace.observe(&config, &cpu(10.0)), 1);
+        }
+    }
+
+    #[test]
+    fn network_noise_and_proportional_changes_are_steady() {
+        let rate = |download_bps| Snapshot { download_bps, ..Snapshot::default() };
+
+        assert!(is_steady(&rate(0), &rate(NETWORK_NOISE_BPS - 1)));
+        assert!(is_steady(&rate(1_000_000), &rate(1_090_000)));
+        assert!(!is_steady(&rate(1_000_000), &rate(1_200_000)));
+        assert!(!is_steady(&rate(0), &rate(NETWORK_NOISE_BPS)));
+    }
+
+    #[test]
+    fn temperature_appearing_is_a_change() {
+        let celsius = |cpu_celsius| Snapshot { cpu_celsius, ..Snapshot::default() };
+
+        assert!(is_steady(&celsius(Some(50.0)), &celsius(Some(51.5))));
+        assert!(!is_steady(&celsius(Some(50.0)), &celsius(Some(52.0))));
+        assert!(!is_steady(&celsius(None), &celsius(Some(50.0))));
+    }
+}
@@ -0,0 +1,64 @@
+//! Power source from UPower, or `/sys/class/power_supply` without it
+
+use std::fs;
+use std::path::Path;
+use zbus::blocking::Connection;
+use zbus::proxy::CacheProperties;
+
+use crate::sysfs::Root;
+
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+pub const SERVICE: &str = "org.freedesktop.UPower";
+pub const PATH: &str = "/org/freedesktop/UPower";
+
+#[zbus::proxy(
+    interface = "org.freedesktop.UPower",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower"
+)]
+trait UPower {
+    #[zbus(property)]
+    fn on_battery(&self) -> zbus::Result<bool>;
+}
+
+/// The system's power daemon
+pub struct Power {
+    upower: UPowerProxyBlocking<'static>,
+}
+
+impl Power {
+    pub fn connect(connection: &Connection) -> zbus::Result<Self> {
+        let upower = UPowerProxyBlocking::builder(connection)
+            .cache_properties(CacheProperties::No)
+            .build()?;
+
+        Ok(Self { upower })
+    }
+
+    /// Whether the machine is running from its battery
+    pub fn on_battery(&self) -> zbus::Result<bool> {
+        self.upower.on_battery()
+    }
+}
+
+/// Whether a system battery is discharging, i.e. the machine is unplugged
+pub fn on_battery(root: &Root) -> bool {
+    let Ok(entries) = fs::read_dir(root.join(POWER_SUPPLY)) else {
+        return false;
+    };
+
+    entries.flatten().any(|entry| {
+        let path = entry.path();
+        read(&path, "type").as_deref() == Some("Battery")
+            // Mice, keyboards and headsets report their own batteries with scope "Device"
+            && read(&path, "scope").as_deref() != Some("Device")
+            && read(&path, "status").as_deref() == Some("Discharging")
+    })
+}
+
+fn read(supply: &Path, attribute: &str) -> Option<String> {
+    fs::read_to_string(supply.join(attribute))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
@@ -0,0 +1,299 @@
+//! Threshold alerts evaluated against sampled values
+
+pub mod notifier;
+
+use std::time::{Duration, Instant};
+
+use crate::config::{AlertCondition, AlertMetric, AlertRule, Units};
+use crate::formatting::*;
+use crate::monitors::Snapshot;
+
+pub use notifier::Notification;
+
+pub struct AlertEngine {
+    rules: Vec<RuleState>,
+    units: Units,
+}
+
+struct RuleState {
+    rule: AlertRule,
+    breach_since: Option<Instant>,
+    /// An alert went out for the current breach
+    firing: bool,
+    last_notified: Option<Instant>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: &[AlertRule], units: &Units) -> Self {
+        Self {
+            rules: rules
+                .iter()
+                .cloned()
+                .map(|rule| RuleState {
+                    rule,
+                    breach_since: None,
+                    firing: false,
+                    last_notified: None,
+                })
+                .collect(),
+            units: units.clone(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Evaluate every rule against the latest sample, returning the notifications to send
+    pub fn evaluate(&mut self, stats: &Snapshot, now: Instant) -> Vec<Notification> {
+        let mut notifications = Vec::new();
+
+        let units = &self.units;
+
+        for state in &mut self.rules {
+            // A sensor that isn't available keeps the rule where it was
+            let Some(value) = metric_value(state.rule.metric, stats) else {
+                continue;
+            };
+
+            if state.rule.condition.is_breached(value, state.rule.threshold) {
+                let since = *state.breach_since.get_or_insert(now);
+                let sustained = now.duration_since(since) >= Duration::from_secs(state.rule.for_secs);
+
+                if sustained && !state.firing {
+                    // Checked on every sample, so a breach outlasting the cooldown still alerts
+                    let cooldown = Duration::from_secs(state.rule.cooldown_secs);
+                    let cooled_down = state
+                        .last_notified
+                        .is_none_or(|last| now.duration_since(last) >= cooldown);
+
+                    if cooled_down {
+                        state.firing = true;
+                        state.last_notified = Some(now);
+                        notifications.push(Notification::alert(
+                            format!("{} {}", metric_name(state.rule.metric), state.rule.condition.describe()),
+                            format!(
+                                "{} for {}s (threshold {})",
+                                format_metric(state.rule.metric, value, units),
+                                state.rule.for_secs,
+                                format_metric(state.rule.metric, state.rule.threshold, units)
+                            ),
+                        ));
+                    }
+                }
+            } else {
+                state.breach_since = None;
+
+                if state.firing {
+                    state.firing = false;
+
+                    if state.rule.notify_recovery {
+                        notifications.push(Notification::recovery(
+                            format!("{} back to normal", metric_name(state.rule.metric)),
+                            format_metric(state.rule.metric, value, units),
+                        ));
+                    }
+                }
+            }
+        }
+
+        notifications
+    }
+}
+
+impl AlertCondition {
+    fn is_breached(self, value: f32, threshold: f32) -> bool {
+        match self {
+            AlertCondition::Above => value > threshold,
+            AlertCondition::Below => value < threshold,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            AlertCondition::Above => "too high",
+            AlertCondition::Below => "too low",
+        }
+    }
+}
+
+fn metric_value(metric: AlertMetric, stats: &Snapshot) -> Option<f32> {
+    match metric {
+        AlertMetric::CpuUsage => Some(stats.cpu_usage),
+        AlertMetric::CpuTemperature => stats.cpu_celsius,
+        AlertMetric::GpuTemperature => stats.gpu_celsius,
+        AlertMetric::MemoryUsage => Some(stats.memory_usage_percent()),
+        // Thresholds for network rules are written in Mbps
+        AlertMetric::NetworkDownload => Some(stats.download_bps as f32 / 125_000.0),
+        AlertMetric::NetworkUpload => Some(stats.upload_bps as f32 / 125_000.0),
+        AlertMetric::DiskFree => stats.disk_free_percent,
+    }
+}
+
+fn metric_name(metric: AlertMetric) -> &'static str {
+    match metric {
+        AlertMetric::CpuUsage => "CPU usage",
+        AlertMetric::CpuTemperature => "CPU temperature",
+        AlertMetric::GpuTemperature => "GPU temperature",
+        AlertMetric::MemoryUsage => "Memory usage",
+        AlertMetric::NetworkDownload => "Download speed",
+        AlertMetric::NetworkUpload => "Upload speed",
+        AlertMetric::DiskFree => "Free disk space",
+    }
+}
+
+fn format_metric(metric: AlertMetric, value: f32, units: &Units) -> String {
+    // Notifications are read on their own, so skip the panel's fixed-width padding
+    let units = Units {
+        fixed_width: false,
+        ..units.clone()
+    };
+
+    match metric {
+        AlertMetric::CpuUsage | AlertMetric::MemoryUsage | AlertMetric::DiskFree => {
+            format_percentage(value, &units)
+        }
+        AlertMetric::CpuTemperature | AlertMetric::GpuTemperature => format_temperature(value, &units),
+        AlertMetric::NetworkDownload | AlertMetric::NetworkUpload => {
+            format_network_speed((value * 125_000.0) as u64, &units)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AlertRule;
+    use crate::test_bus::TestBus;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use zbus::connection::Builder;
+    use zbus::zvariant::Value;
+
+    /// Stands in for the notification daemon, keeping each summary and urgency it receives
+    #[derive(Clone, Default)]
+    struct Daemon {
+        received: Arc<Mutex<Vec<(String, u8)>>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl Daemon {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: &str,
+            _replaces_id: u32,
+            _app_icon: &str,
+            summary: &str,
+            _body: &str,
+            _actions: Vec<&str>,
+            hints: HashMap<&str, Value<'_>>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let urgency = match hints.get("urgency") {
+                Some(Value::U8(urgency)) => *urgency,
+                _ => 0,
+            };
+            let mut received = self.received.lock().unwrap();
+            received.push((summary.to_string(), urgency));
+            received.len() as u32
+        }
+    }
+
+    fn cpu(usage: f32) -> Snapshot {
+        Snapshot {
+            cpu_usage: usage,
+            ..Snapshot::default()
+        }
+    }
+
+    #[test]
+    fn notifies_after_sustained_breach_respecting_cooldown_and_recovery() {
+        let Some(bus) = TestBus::start() else {
+            return;
+        };
+        let daemon = Daemon::default();
+        let _server = zbus::block_on(
+            Builder::address(bus.address.as_str())
+                .unwrap()
+                .name("org.freedesktop.Notifications")
+                .unwrap()
+                .serve_at("/org/freedesktop/Notifications", daemon.clone())
+                .unwrap()
+                .build(),
+        )
+        .unwrap();
+        let client = zbus::block_on(Builder::address(bus.address.as_str()).unwrap().build()).unwrap();
+
+        let rule = AlertRule {
+            metric: AlertMetric::CpuUsage,
+            condition: AlertCondition::Above,
+            threshold: 90.0,
+            for_secs: 10,
+            cooldown_secs: 60,
+            notify_recovery: true,
+        };
+        let mut engine = AlertEngine::new(&[rule], &Units::default());
+        let start = Instant::now();
+
+        let samples = [
+            // Breached, but not for long enough yet
+            (0, 95.0),
+            (5, 95.0),
+            (10, 95.0),
+            (12, 95.0),
+            (15, 50.0),
+            // Sustained again, but within the cooldown, so neither alert nor recovery
+            (20, 95.0),
+            (30, 95.0),
+            (35, 50.0),
+            // Sustained from 50 and still going when the cooldown ends at 72
+            (40, 95.0),
+            (50, 95.0),
+            (71, 95.0),
+            (72, 95.0),
+            (80, 50.0),
+        ];
+        for (seconds, usage) in samples {
+            let now = start + Duration::from_secs(seconds);
+            for notification in engine.evaluate(&cpu(usage), now) {
+                zbus::block_on(notifier::send_with(&client, &notification)).unwrap();
+            }
+        }
+
+        let received = daemon.received.lock().unwrap().clone();
+        assert_eq!(
+            received,
+            [
+                ("CPU usage too high".to_string(), notifier::Urgency::Critical as u8),
+                ("CPU usage back to normal".to_string(), notifier::Urgency::Normal as u8),
+                ("CPU usage too high".to_string(), notifier::Urgency::Critical as u8),
+                ("CPU usage back to normal".to_string(), notifier::Urgency::Normal as u8),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_sensor_keeps_rule_state() {
+        let rule = AlertRule {
+            metric: AlertMetric::DiskFree,
+            condition: AlertCondition::Below,
+            threshold: 5.0,
+            for_secs: 0,
+            cooldown_secs: 0,
+            notify_recovery: false,
+        };
+        let mut engine = AlertEngine::new(&[rule], &Units::default());
+        let now = Instant::now();
+
+        assert!(engine.evaluate(&Snapshot::default(), now).is_empty());
+
+        let full = Snapshot {
+            disk_free_percent: Some(2.0),
+            ..Snapshot::default()
+        };
+        let notifications = engine.evaluate(&full, now);
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].summary, "Free disk space too low");
+    }
+}
@@ -0,0 +1,103 @@
+//! Desktop notifications over the freedesktop D-Bus interface
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use zbus::zvariant::Value;
+use zbus::Connection;
+
+const APP_NAME: &str = "System Stats";
+const APP_ICON: &str = "io.github.rylan_x.cosmic-applet-systemstats";
+
+#[zbus::proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Normal = 1,
+    Critical = 2,
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    pub urgency: Urgency,
+}
+
+impl Notification {
+    pub fn alert(summary: String, body: String) -> Self {
+        Self {
+            summary,
+            body,
+            urgency: Urgency::Critical,
+        }
+    }
+
+    pub fn recovery(summary: String, body: String) -> Self {
+        Self {
+            summary,
+            body,
+            urgency: Urgency::Normal,
+        }
+    }
+}
+
+/// Session bus connection reused by every notification, opened by the first one
+static SESSION: Mutex<Option<Connection>> = Mutex::new(None);
+
+/// Send a notification on the session bus, returning the server-assigned id
+pub async fn send(notification: Notification) -> zbus::Result<u32> {
+    send_with(&session().await?, &notification).await
+}
+
+async fn session() -> zbus::Result<Connection> {
+    if let Some(connection) = SESSION.lock().ok().and_then(|session| session.clone()) {
+        return Ok(connection);
+    }
+
+    // Two notifications racing here both connect; the later one is kept, which is harmless
+    let connection = Connection::session().await?;
+    if let Ok(mut session) = SESSION.lock() {
+        *session = Some(connection.clone());
+    }
+    Ok(connection)
+}
+
+/// Send a notification over a given connection rather than the shared session one
+pub async fn send_with(connection: &Connection, notification: &Notification) -> zbus::Result<u32> {
+    let proxy = NotificationsProxy::new(connection).await?;
+
+    let mut hints = HashMap::new();
+    hints.insert("urgency", Value::U8(notification.urgency as u8));
+
+    proxy
+        .notify(
+            APP_NAME,
+            0,
+            APP_ICON,
+            &notification.summary,
+            &notification.body,
+            &[],
+            hints,
+            -1,
+        )
+        .await
+}
@@ -1,33 +1,425 @@
 use cosmic::app::{Core, Task};
-use cosmic::iced::{Alignment, Limits, Subscription};
-use cosmic::iced::time;
+use cosmic::cosmic_config::{self, CosmicConfigEntry};
+use cosmic::iced::platform_specific::shell::commands::popup::{destroy_popup, get_popup};
+use cosmic::iced::mouse::ScrollDelta;
+use cosmic::iced::{time, window, Alignment, Color, Length, Limits, Padding, Subscription};
 use cosmic::iced_core::text::Wrapping;
-use cosmic::iced_widget::Row;
-use cosmic::widget::{autosize, text};
+use cosmic::iced_widget::{mouse_area, svg, Column, Row, Stack, Text};
+use cosmic::widget::{autosize, button, container, icon, text};
 use cosmic::Element;
-use std::time::Duration;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use crate::config::Config;
-use crate::formatting::*;
-use crate::monitors::MonitorStats;
+use crate::actions;
+use crate::alerts::{self, AlertEngine};
+use crate::collector;
+use crate::config::{Click, ClickAction, Config, Issue, LabelStyle};
+use crate::icons;
+use crate::layout::{self, Direction, Layout, Orientation};
+use crate::monitors::Snapshot;
+use crate::segments::{self, Segment, SegmentKind};
+use crate::settings;
+use crate::template::Template;
+use crate::thresholds::ThresholdState;
 
-const ID: &str = "com.github.rylan-x.systemstats";
+pub const ID: &str = "com.github.rylan-x.systemstats";
+
+/// Touchpad scrolling this far counts as one scroll step
+const SCROLL_STEP_PIXELS: f32 = 40.0;
 
 pub struct SystemStats {
     core: Core,
-    monitors: MonitorStats,
+    /// Latest readings from the collector
+    snapshot: Snapshot,
+    collector: Option<collector::Handle>,
+    collector_options: collector::Options,
+    alerts: AlertEngine,
+    config_handler: Option<cosmic_config::Config>,
     config: Config,
+    /// Problems found in the current config, shown in the panel and popup
+    issues: Vec<Issue>,
+    popup: Option<window::Id>,
+    drafts: settings::Drafts,
+    /// Abbreviated values in the panel, switched by a click action
+    compact: bool,
+    /// Touchpad scrolling not yet amounting to a step
+    scrolled: f32,
+    /// Index into `config.pages.groups` of the segments on show
+    page: usize,
+    /// When the current page came up, so a page turned by hand isn't turned again at once
+    page_shown: Instant,
+}
+
+pub struct Flags {
+    pub config_handler: Option<cosmic_config::Config>,
+    pub config: Config,
+    pub issues: Vec<Issue>,
+    pub collector: collector::Options,
 }
 
 /// Messages the applet can receive
 #[derive(Debug, Clone)]
 pub enum Message {
-    Tick,
+    Collector(collector::Event),
+    NotificationSent(Result<u32, String>),
+    ConfigChanged(Box<Config>, Vec<Issue>),
+    /// A mouse button was pressed, over a segment or elsewhere on the applet
+    Clicked(Click, Option<SegmentKind>),
+    Scrolled(ScrollDelta, Option<SegmentKind>),
+    /// Another `pages.interval_secs` went by
+    PageTimer,
+    /// Opens and closes hover tooltips
+    Surface(cosmic::surface::Action),
+    PopupClosed(window::Id),
+    Settings(settings::Message),
+}
+
+impl SystemStats {
+    fn apply_config(&mut self, config: Config) {
+        if let Some(collector) = &self.collector {
+            collector.reconfigure(&config);
+        }
+
+        // Rebuilding the engine resets pending breaches, so only do it when rules change
+        if config.alerts != self.config.alerts || config.units != self.config.units {
+            self.alerts = AlertEngine::new(&config.alerts, &config.units);
+        }
+
+        if config.pages != self.config.pages {
+            self.page = 0;
+            self.page_shown = Instant::now();
+        }
+
+        self.config = config;
+    }
+
+    /// Move `step` pages forwards or backwards, wrapping around
+    fn turn_page(&mut self, step: isize) {
+        let count = self.config.pages.groups.len();
+        if count < 2 {
+            return;
+        }
+        self.page = (self.page as isize + step).rem_euclid(count as isize) as usize;
+        self.page_shown = Instant::now();
+    }
+
+    /// Segments to show, limited to the current page when pages are set
+    fn visible_segments(&self) -> Vec<Segment> {
+        let segments = segments::build(&self.snapshot, &self.config);
+        match self.config.pages.groups.get(self.page) {
+            Some(page) => segments
+                .into_iter()
+                .filter(|segment| page.contains(&segment.kind))
+                .collect(),
+            None => segments,
+        }
+    }
+
+    /// A dot per page, the current one filled
+    fn page_indicator<'a>(&self) -> Option<Element<'a, Message>> {
+        let count = self.config.pages.groups.len();
+        if count < 2 {
+            return None;
+        }
+        let dots: String = (0..count)
+            .map(|page| if page == self.page { '●' } else { '○' })
+            .collect();
+        Some(text(dots).size(6).wrapping(Wrapping::None).into())
+    }
+
+    fn perform(&mut self, action: ClickAction) -> Task<Message> {
+        match action {
+            ClickAction::None => {}
+            ClickAction::Settings => return self.toggle_popup(),
+            ClickAction::ToggleCompact => self.compact = !self.compact,
+            ClickAction::Command(command) => actions::launch(&command, false),
+            ClickAction::Terminal(command) => actions::launch(&command, true),
+            ClickAction::NextPage => self.turn_page(1),
+            ClickAction::PreviousPage => self.turn_page(-1),
+        }
+        Task::none()
+    }
+
+    /// Which way a scroll event went, once it amounts to a whole step
+    fn scroll_step(&mut self, delta: ScrollDelta) -> Option<Click> {
+        let y = match delta {
+            ScrollDelta::Lines { y, .. } => y,
+            ScrollDelta::Pixels { y, .. } => {
+                self.scrolled += y;
+                if self.scrolled.abs() < SCROLL_STEP_PIXELS {
+                    return None;
+                }
+                std::mem::take(&mut self.scrolled)
+            }
+        };
+
+        if y > 0.0 {
+            Some(Click::ScrollUp)
+        } else if y < 0.0 {
+            Some(Click::ScrollDown)
+        } else {
+            None
+        }
+    }
+
+    fn toggle_popup(&mut self) -> Task<Message> {
+        if let Some(id) = self.popup.take() {
+            return destroy_popup(id);
+        }
+
+        let Some(main_id) = self.core.main_window_id() else {
+            return Task::none();
+        };
+        let id = window::Id::unique();
+        self.popup = Some(id);
+        self.drafts.clear();
+
+        let mut popup_settings = self.core.applet.get_popup_settings(main_id, id, None, None, None);
+        popup_settings.positioner.size_limits = Limits::NONE
+            .min_width(300.0)
+            .max_width(400.0)
+            .min_height(200.0)
+            .max_height(800.0);
+        get_popup(popup_settings)
+    }
+
+    /// The whole applet, handling every click no segment overrides
+    fn clickable<'a>(&self, content: impl Into<Element<'a, Message>>) -> Element<'a, Message> {
+        let content = button::custom(content)
+            .class(cosmic::theme::Button::AppletIcon)
+            .on_press(Message::Clicked(Click::Left, None));
+
+        mouse_area(content)
+            .on_middle_press(Message::Clicked(Click::Middle, None))
+            .on_right_press(Message::Clicked(Click::Right, None))
+            .on_scroll(|delta| Message::Scrolled(delta, None))
+            .into()
+    }
+
+    /// Full details of a segment while the pointer is over it, unless the popup is open
+    fn segment_hover<'a>(
+        &self,
+        content: Element<'a, Message>,
+        kind: SegmentKind,
+    ) -> Element<'a, Message> {
+        let details = segments::details(kind, &self.snapshot, &self.config);
+        self.hover(content, details)
+    }
+
+    fn hover<'a>(&self, content: Element<'a, Message>, details: String) -> Element<'a, Message> {
+        if details.is_empty() {
+            return content;
+        }
+        self.core
+            .applet
+            .applet_tooltip(content, details, self.popup.is_some(), Message::Surface, None)
+            .into()
+    }
+
+    /// A segment that takes the clicks it has its own actions for
+    fn segment_clicks<'a>(
+        &self,
+        content: Element<'a, Message>,
+        kind: SegmentKind,
+    ) -> Element<'a, Message> {
+        let overrides = self.config.clicks.segment(kind);
+        if overrides.is_empty() {
+            return content;
+        }
+
+        let segment = Some(kind);
+        let mut area = mouse_area(content);
+        if overrides.left.is_some() {
+            area = area.on_press(Message::Clicked(Click::Left, segment));
+        }
+        if overrides.middle.is_some() {
+            area = area.on_middle_press(Message::Clicked(Click::Middle, segment));
+        }
+        if overrides.right.is_some() {
+            area = area.on_right_press(Message::Clicked(Click::Right, segment));
+        }
+        if overrides.scroll_up.is_some() || overrides.scroll_down.is_some() {
+            area = area.on_scroll(move |delta| Message::Scrolled(delta, segment));
+        }
+        area.into()
+    }
+
+    /// Shown in the panel while the config has problems; details are in the popup
+    fn warning_icon<'a>(&self) -> Option<Element<'a, Message>> {
+        if self.issues.is_empty() {
+            return None;
+        }
+
+        let icon = icon::from_name("dialog-warning-symbolic")
+            .size(self.core.applet.suggested_size(true).0)
+            .symbolic(true)
+            .icon()
+            .class(cosmic::theme::Svg::Custom(Rc::new(|theme| svg::Style {
+                color: Some(theme.cosmic().warning_color().into()),
+            })));
+        Some(icon.into())
+    }
+
+    fn layout(&self) -> Layout {
+        let orientation = if self.core.applet.is_horizontal() {
+            Orientation::Horizontal
+        } else {
+            Orientation::Vertical
+        };
+        layout::choose(orientation, self.core.applet.suggested_size(true).0)
+    }
+
+    /// A custom format, one row per line of the template
+    fn template_view<'a>(&self, template: &Template) -> Element<'a, Message> {
+        let mut lines = vec![Vec::new()];
+        for part in template.parts(&self.snapshot, &self.config) {
+            if part.widest.is_some() {
+                lines.last_mut().unwrap().push(self.stable_value(part.text, part.widest));
+                continue;
+            }
+            for (index, line) in part.text.split('\n').enumerate() {
+                if index > 0 {
+                    lines.push(Vec::new());
+                }
+                if !line.is_empty() {
+                    lines.last_mut().unwrap().push(self.value_text(line.to_string()).into());
+                }
+            }
+        }
+
+        let rows = lines
+            .into_iter()
+            .map(|line| Row::from_vec(line).align_y(Alignment::Center).into())
+            .collect();
+        let content = Column::from_vec(rows).align_x(Alignment::Center);
+
+        // The template mixes segments, so its tooltip covers all of them
+        let details = segments::build(&self.snapshot, &self.config)
+            .iter()
+            .map(|segment| segments::details(segment.kind, &self.snapshot, &self.config))
+            .filter(|details| !details.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        self.hover(content.into(), details)
+    }
+
+    /// Stacked layout for vertical panels and docks
+    fn column_view(&self, layout: Layout) -> Element<'_, Message> {
+        let icon_size = self.core.applet.suggested_size(true).0;
+
+        let body: Vec<Element<'_, Message>> = match &self.config.format {
+            Some(template) => vec![self.template_view(template)],
+            None => self
+                .visible_segments()
+                .into_iter()
+                .map(|segment| {
+                    let header: Element<'_, Message> = if layout.shows_icon(segment.style) {
+                        segment_icon(&segment, icon_size)
+                    } else {
+                        text(segment.label).wrapping(Wrapping::None).into()
+                    };
+                    let kind = segment.kind;
+                    let value = if layout.abbreviate { segment.short } else { segment.value };
+
+                    let item = Column::from_vec(vec![header, self.value_text(value).into()])
+                        .spacing(2)
+                        .align_x(Alignment::Center);
+                    self.segment_clicks(self.segment_hover(item.into(), kind), kind)
+                })
+                .collect(),
+        };
+
+        let items = self
+            .warning_icon()
+            .into_iter()
+            .chain(body)
+            .chain(self.page_indicator())
+            .collect();
+
+        let content = Column::from_vec(items)
+            .padding([8, 0])
+            .spacing(8)
+            .align_x(Alignment::Center);
+        let content = self.clickable(content);
+
+        let limits = Limits::NONE
+            .min_width(1.0)
+            .max_width(128.0)
+            .max_height(600.0);
+
+        autosize::autosize(content, cosmic::widget::Id::unique())
+            .limits(limits)
+            .into()
+    }
+
+    fn value_text<'a>(&self, content: String) -> Text<'a, cosmic::Theme, cosmic::Renderer> {
+        let widget = text(content).wrapping(Wrapping::None);
+        if self.config.display.monospace {
+            widget.font(cosmic::font::mono())
+        } else {
+            widget
+        }
+    }
+
+    /// A segment's label and value, with room reserved for its widest value
+    fn segment_view<'a>(&self, segment: &Segment) -> Element<'a, Message> {
+        let label: Element<'a, Message> = match segment.style {
+            LabelStyle::Text => text(format!("{}: ", segment.label)).wrapping(Wrapping::None).into(),
+            LabelStyle::Icon => segment_icon(segment, self.core.applet.suggested_size(true).0),
+        };
+        let spacing = match segment.style {
+            LabelStyle::Text => 0,
+            LabelStyle::Icon => 4,
+        };
+
+        let value = if self.compact {
+            self.value_text(segment.short.clone()).into()
+        } else {
+            self.stable_value(segment.value.clone(), Some(segment.widest.clone()))
+        };
+
+        Row::from_vec(vec![label, value])
+            .spacing(spacing)
+            .align_y(Alignment::Center)
+            .into()
+    }
+
+    /// `value` over an invisible copy of `widest`, when stable width is on
+    fn stable_value<'a>(&self, value: String, widest: Option<String>) -> Element<'a, Message> {
+        let Some(widest) = widest.filter(|_| self.config.display.stable_width) else {
+            return self.value_text(value).into();
+        };
+
+        let reserved = self
+            .value_text(widest)
+            .class(cosmic::theme::Text::Color(Color::TRANSPARENT));
+        let current = container(self.value_text(value))
+            .width(Length::Fill)
+            .align_x(Alignment::End);
+
+        Stack::with_children(vec![reserved.into(), current.into()]).into()
+    }
+}
+
+/// Bundled icon for a segment, tinted when its value crosses a threshold
+fn segment_icon<'a>(segment: &Segment, size: u16) -> Element<'a, Message> {
+    let icon = icon::icon(icons::segment_icon(segment.kind)).size(size);
+
+    let tint: fn(&cosmic::Theme) -> Color = match segment.state {
+        ThresholdState::Normal => return icon.into(),
+        ThresholdState::Warning => |theme| theme.cosmic().warning_color().into(),
+        ThresholdState::Critical => |theme| theme.cosmic().destructive_color().into(),
+    };
+
+    icon.class(cosmic::theme::Svg::Custom(Rc::new(move |theme| svg::Style {
+        color: Some(tint(theme)),
+    })))
+    .into()
 }
 
 impl cosmic::Application for SystemStats {
     type Executor = cosmic::executor::Default;
-    type Flags = Config;
+    type Flags = Flags;
     type Message = Message;
     const APP_ID: &'static str = ID;
 
@@ -39,78 +431,140 @@ impl cosmic::Application for SystemStats {
         &mut self.core
     }
 
-    fn init(core: Core, config: Self::Flags) -> (Self, Task<Self::Message>) {
+    fn init(core: Core, flags: Self::Flags) -> (Self, Task<Self::Message>) {
+        let config = flags.config;
         let app = SystemStats {
             core,
-            monitors: MonitorStats::new(&config),
+            snapshot: Snapshot::default(),
+            collector: None,
+            collector_options: flags.collector,
+            alerts: AlertEngine::new(&config.alerts, &config.units),
+            config_handler: flags.config_handler,
             config,
+            issues: flags.issues,
+            popup: None,
+            drafts: settings::Drafts::default(),
+            compact: false,
+            scrolled: 0.0,
+            page: 0,
+            page_shown: Instant::now(),
         };
         (app, Task::none())
     }
 
     fn update(&mut self, message: Self::Message) -> Task<Self::Message> {
         match message {
-            Message::Tick => {
-                self.monitors.update(&self.config);
+            Message::Collector(collector::Event::Started(collector)) => {
+                collector.reconfigure(&self.config);
+                self.collector = Some(collector);
             }
-        }
-        Task::none()
-    }
+            Message::Collector(collector::Event::Sampled(snapshot)) => {
+                self.snapshot = snapshot;
 
-    fn view(&self) -> Element<'_, Self::Message> {
-        let mut parts = Vec::new();
-
-        // CPU
-        if self.config.monitors.cpu_usage || self.config.monitors.cpu_temperature {
-            let mut cpu_parts = Vec::new();
-
-            if self.config.monitors.cpu_usage {
-                cpu_parts.push(format_percentage(self.monitors.cpu.usage()));
+                if !self.alerts.is_empty() {
+                    let notifications = self.alerts.evaluate(&self.snapshot, Instant::now());
+                    return Task::batch(notifications.into_iter().map(|notification| {
+                        cosmic::task::future(async move {
+                            Message::NotificationSent(
+                                alerts::notifier::send(notification)
+                                    .await
+                                    .map_err(|e| e.to_string()),
+                            )
+                        })
+                    }));
+                }
             }
-
-            if self.config.monitors.cpu_temperature {
-                if let Some(temp) = self.monitors.temperature.cpu_celsius() {
-                    cpu_parts.push(format_celsius(temp));
+            Message::NotificationSent(result) => {
+                if let Err(e) = result {
+                    log::warn!("Failed to send alert notification: {}", e);
                 }
             }
-
-            if !cpu_parts.is_empty() {
-                parts.push(format!("CPU: {}", cpu_parts.join(" | ")));
+            Message::ConfigChanged(config, issues) => {
+                for issue in &issues {
+                    log::warn!("Config: {}", issue);
+                }
+                self.issues = issues;
+                self.apply_config(*config);
             }
-        }
-
-        // GPU temperature 
-        if self.config.monitors.gpu_temperature {
-            if let Some(temp) = self.monitors.temperature.gpu_celsius() {
-                parts.push(format!("GPU: {}", format_celsius(temp)));
+            Message::Clicked(click, segment) => {
+                let action = self.config.clicks.action(click, segment).clone();
+                return self.perform(action);
+            }
+            Message::Scrolled(delta, segment) => {
+                if let Some(click) = self.scroll_step(delta) {
+                    let action = self.config.clicks.action(click, segment).clone();
+                    return self.perform(action);
+                }
+            }
+            Message::PageTimer => {
+                let interval = Duration::from_secs(self.config.pages.interval_secs);
+                if self.page_shown.elapsed() >= interval / 2 {
+                    self.turn_page(1);
+                }
+            }
+            Message::Surface(action) => {
+                return cosmic::task::message(cosmic::Action::Cosmic(cosmic::app::Action::Surface(
+                    action,
+                )));
+            }
+            Message::PopupClosed(id) => {
+                if self.popup == Some(id) {
+                    self.popup = None;
+                }
+            }
+            Message::Settings(message) => {
+                if let Some(config) = settings::update(&self.config, &mut self.drafts, message) {
+                    if let Some(handler) = &self.config_handler {
+                        if let Err(e) = config.write_entry(handler) {
+                            log::warn!("Failed to save settings: {}", e);
+                        }
+                    }
+                    self.apply_config(config);
+                }
             }
         }
+        Task::none()
+    }
 
-        // Memory
-        if self.config.monitors.memory {
-            parts.push(format!("RAM: {}/{}",
-                format_memory_gb(self.monitors.memory.used_gb()),
-                format_memory_gb(self.monitors.memory.total_gb())));
-        }
+    fn view_window(&self, _id: window::Id) -> Element<'_, Self::Message> {
+        let content = settings::view(&self.config, &self.issues, &self.drafts).map(Message::Settings);
+        self.core.applet.popup_container(content).into()
+    }
 
-        // Network
-        if self.config.monitors.network {
-            parts.push(format!("Net: ↓{} ↑{}",
-                format_network_speed(self.monitors.network.download_bps()),
-                format_network_speed(self.monitors.network.upload_bps())));
-        }
+    fn on_close_requested(&self, id: window::Id) -> Option<Self::Message> {
+        Some(Message::PopupClosed(id))
+    }
 
-        let stats_text = parts.join(" | ");
+    fn view(&self) -> Element<'_, Self::Message> {
+        let layout = self.layout();
+        if layout.direction == Direction::Column {
+            return self.column_view(layout);
+        }
 
-        let elements = vec![
-            text(stats_text)
-                .wrapping(Wrapping::None)
-                .into()
-        ];
+        let mut elements = self.warning_icon().into_iter().collect::<Vec<_>>();
+        match &self.config.format {
+            Some(template) => elements.push(self.template_view(template)),
+            None => {
+                let mut separator = false;
+                for segment in self.visible_segments() {
+                    if separator {
+                        elements.push(text(" | ").wrapping(Wrapping::None).into());
+                    }
+                    let view = self.segment_hover(self.segment_view(&segment), segment.kind);
+                    elements.push(self.segment_clicks(view, segment.kind));
+                    separator = true;
+                }
+                if let Some(indicator) = self.page_indicator() {
+                    let padding = Padding { left: 6.0, ..Padding::ZERO };
+                    elements.push(container(indicator).padding(padding).into());
+                }
+            }
+        }
 
         let content = Row::from_vec(elements)
             .padding([0, 8])
             .align_y(Alignment::Center);
+        let content = self.clickable(content);
 
         let limits = Limits::NONE
             .max_width(600.0)
@@ -127,6 +581,21 @@ impl cosmic::Application for SystemStats {
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
-        time::every(Duration::from_millis(self.config.refresh_interval_ms)).map(|_| Message::Tick)
+        let mut subscriptions = vec![
+            collector::subscription(self.collector_options.clone()).map(Message::Collector),
+            self.core.watch_config::<Config>(ID).map(|update| {
+                let (config, issues) = update.config.checked(update.errors);
+                Message::ConfigChanged(Box::new(config), issues)
+            }),
+        ];
+
+        // Turns on time whatever the sampling pace, which adaptive refresh may slow right down
+        let pages = &self.config.pages;
+        if pages.interval_secs > 0 && pages.groups.len() > 1 {
+            let interval = Duration::from_secs(pages.interval_secs);
+            subscriptions.push(time::every(interval).map(|_| Message::PageTimer));
+        }
+
+        Subscription::batch(subscriptions)
     }
 }
@@ -0,0 +1,184 @@
+//! Command-line flags
+
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::cli::Output;
+use crate::socket::Request;
+
+pub const USAGE: &str = "\
+Usage: cosmic-applet-systemstats [OPTIONS]
+
+Options:
+  --cli                Print stats to stdout instead of running as an applet
+  --once               With --cli, print a single line and exit
+  --format <TEMPLATE>  With --cli, use this format instead of the configured one
+  --output <KIND>      With --cli, print plain text (default), waybar or i3bar JSON
+  --record <FILE>      Save every sample to FILE as JSON Lines
+  --replay <FILE>      Show samples from a recording instead of live sensors
+  --query <REQUEST>    Ask the running applet for a snapshot, its history, or
+                       subscribe to new samples, printing JSON Lines
+  --seconds <N>        With --query history, only the last N seconds
+  -h, --help           Print this help";
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub help: bool,
+    pub cli: bool,
+    pub once: bool,
+    pub format: Option<String>,
+    pub output: Output,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub query: Option<Request>,
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        Self::parse_from(env::args_os().skip(1))
+    }
+
+    fn parse_from(mut args: impl Iterator<Item = OsString>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut seconds = None;
+
+        while let Some(arg) = args.next() {
+            match arg.to_str() {
+                Some("-h" | "--help") => parsed.help = true,
+                Some("--cli") => parsed.cli = true,
+                Some("--once") => parsed.once = true,
+                Some("--format") => parsed.format = Some(string_value("--format", args.next())?),
+                Some("--output") => parsed.output = output_value(args.next())?,
+                Some("--record") => parsed.record = Some(path_value("--record", args.next())?),
+                Some("--replay") => parsed.replay = Some(path_value("--replay", args.next())?),
+                Some("--query") => parsed.query = Some(query_value(args.next())?),
+                Some("--seconds") => seconds = Some(seconds_value(args.next())?),
+                _ => return Err(format!("unexpected argument '{}'", arg.to_string_lossy())),
+            }
+        }
+
+        if parsed.record.is_some() && parsed.replay.is_some() {
+            return Err("--record and --replay can't be used together".to_string());
+        }
+        let cli_only = parsed.once || parsed.format.is_some() || parsed.output != Output::Plain;
+        if cli_only && !parsed.cli {
+            return Err("--once, --format and --output need --cli".to_string());
+        }
+        if parsed.cli && (parsed.record.is_some() || parsed.replay.is_some()) {
+            return Err("--record and --replay only apply to the applet".to_string());
+        }
+
+        if let Some(seconds) = seconds {
+            match &mut parsed.query {
+                Some(Request::History { seconds: limit }) => *limit = Some(seconds),
+                _ => return Err("--seconds needs --query history".to_string()),
+            }
+        }
+        let applet_flags = parsed.cli || parsed.record.is_some() || parsed.replay.is_some();
+        if parsed.query.is_some() && applet_flags {
+            return Err("--query can't be combined with --cli, --record or --replay".to_string());
+        }
+
+        Ok(parsed)
+    }
+}
+
+fn path_value(flag: &str, value: Option<OsString>) -> Result<PathBuf, String> {
+    value
+        .map(PathBuf::from)
+        .ok_or_else(|| format!("{} needs a file path", flag))
+}
+
+fn string_value(flag: &str, value: Option<OsString>) -> Result<String, String> {
+    value
+        .ok_or_else(|| format!("{} needs a value", flag))?
+        .into_string()
+        .map_err(|_| format!("{} must be valid UTF-8", flag))
+}
+
+fn output_value(value: Option<OsString>) -> Result<Output, String> {
+    match string_value("--output", value)?.as_str() {
+        "plain" => Ok(Output::Plain),
+        "waybar" => Ok(Output::Waybar),
+        "i3bar" => Ok(Output::I3bar),
+        other => Err(format!("unknown --output '{}', expected plain, waybar or i3bar", other)),
+    }
+}
+
+fn query_value(value: Option<OsString>) -> Result<Request, String> {
+    match string_value("--query", value)?.as_str() {
+        "snapshot" => Ok(Request::Snapshot),
+        "history" => Ok(Request::History { seconds: None }),
+        "subscribe" => Ok(Request::Subscribe),
+        other => Err(format!(
+            "unknown --query '{}', expected snapshot, history or subscribe",
+            other
+        )),
+    }
+}
+
+fn seconds_value(value: Option<OsString>) -> Result<u64, String> {
+    let value = string_value("--seconds", value)?;
+    value
+        .parse()
+        .map_err(|_| format!("--seconds needs a whole number, got '{}'", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse_from(args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn no_flags_run_the_applet() {
+        let args = parse(&[]).unwrap();
+        assert!(!args.cli && !args.help);
+        assert_eq!(args.output, Output::Plain);
+        assert_eq!(args.query, None);
+    }
+
+    #[test]
+    fn cli_flags_are_read() {
+        let args = ["--cli", "--once", "--format", "{cpu.usage}", "--output", "i3bar"];
+        let args = parse(&args).unwrap();
+        assert!(args.cli && args.once);
+        assert_eq!(args.format.as_deref(), Some("{cpu.usage}"));
+        assert_eq!(args.output, Output::I3bar);
+
+        assert!(parse(&["-h"]).unwrap().help);
+        assert_eq!(parse(&["--record", "out.jsonl"]).unwrap().record, Some("out.jsonl".into()));
+    }
+
+    #[test]
+    fn query_takes_seconds_for_history_only() {
+        let args = parse(&["--query", "history", "--seconds", "300"]).unwrap();
+        assert_eq!(args.query, Some(Request::History { seconds: Some(300) }));
+        assert_eq!(parse(&["--query", "subscribe"]).unwrap().query, Some(Request::Subscribe));
+
+        assert!(parse(&["--query", "snapshot", "--seconds", "5"]).is_err());
+        assert!(parse(&["--seconds", "5"]).is_err());
+        assert!(parse(&["--query", "history", "--seconds", "soon"]).is_err());
+        assert!(parse(&["--query", "everything"]).is_err());
+    }
+
+    #[test]
+    fn conflicting_or_incomplete_flags_are_rejected() {
+        for args in [
+            &["--bogus"][..],
+            &["--format"],
+            &["--output", "xml", "--cli"],
+            &["--once"],
+            &["--output", "waybar"],
+            &["--record", "a", "--replay", "b"],
+            &["--cli", "--replay", "b"],
+            &["--cli", "--query", "snapshot"],
+            &["--record", "a", "--query", "snapshot"],
+        ] {
+            assert!(parse(args).is_err(), "{:?} was accepted", args);
+        }
+    }
+}
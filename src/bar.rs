@@ -0,0 +1,210 @@
+//! Status bar output for Waybar custom modules and the i3bar protocol
+//!
+//! Both are built from the same segments as the panel, with each segment's
+//! threshold state mapped to a CSS class (Waybar) or a colour (i3bar).
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::monitors::Snapshot;
+use crate::segments::{self, Segment, SegmentKind};
+use crate::thresholds::ThresholdState;
+
+/// i3bar colours for the warning and critical states; critical blocks are also marked urgent
+const I3BAR_WARNING_COLOR: &str = "#FFB000";
+const I3BAR_CRITICAL_COLOR: &str = "#FF4040";
+
+/// One line of a Waybar `return-type = "json"` custom module
+#[derive(Debug, Serialize)]
+struct WaybarLine {
+    text: String,
+    tooltip: String,
+    /// `normal`, `warning` or `critical`, for styling with `#custom-systemstats.warning` etc.
+    class: &'static str,
+    /// CPU usage when shown, otherwise memory usage; drives `format-icons`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percentage: Option<u8>,
+}
+
+#[derive(Debug, Serialize)]
+struct I3barBlock {
+    name: &'static str,
+    full_text: String,
+    short_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<&'static str>,
+    urgent: bool,
+}
+
+/// Header and opening of the endless array that starts an i3bar stream
+pub const I3BAR_HEADER: &str = "{\"version\":1}\n[";
+
+pub fn waybar(stats: &Snapshot, config: &Config) -> String {
+    let segments = segments::build(stats, config);
+    let state = segments.iter().map(|segment| segment.state).max().unwrap_or_default();
+
+    let percentage = if config.monitors.cpu_usage {
+        Some(stats.cpu_usage)
+    } else if config.monitors.memory {
+        Some(stats.memory_usage_percent())
+    } else {
+        None
+    };
+
+    let line = WaybarLine {
+        text: segments::plain_line(stats, config),
+        tooltip: segments
+            .iter()
+            .map(|segment| format!("{}: {}", segment.label, segment.value))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        class: class(state),
+        percentage: percentage.map(|percent| percent.clamp(0.0, 100.0).round() as u8),
+    };
+
+    serde_json::to_string(&line).unwrap_or_default()
+}
+
+/// One status line: an array of blocks, one per segment or a single one for a custom format
+pub fn i3bar(stats: &Snapshot, config: &Config) -> String {
+    let segments = segments::build(stats, config);
+
+    let blocks = match &config.format {
+        Some(template) => {
+            let text = template.render(stats, config);
+            let state = segments.iter().map(|segment| segment.state).max().unwrap_or_default();
+            vec![block("systemstats", text.clone(), text, state)]
+        }
+        None => segments.iter().map(i3bar_block).collect(),
+    };
+
+    serde_json::to_string(&blocks).unwrap_or_default()
+}
+
+fn i3bar_block(segment: &Segment) -> I3barBlock {
+    let name = match segment.kind {
+        SegmentKind::Cpu => "cpu",
+        SegmentKind::Gpu => "gpu",
+        SegmentKind::Memory => "memory",
+        SegmentKind::Network => "network",
+    };
+
+    block(
+        name,
+        format!("{}: {}", segment.label, segment.value),
+        // Short values are stacked for vertical panels
+        segment.short.replace('\n', " "),
+        segment.state,
+    )
+}
+
+fn block(name: &'static str, full_text: String, short_text: String, state: ThresholdState) -> I3barBlock {
+    I3barBlock {
+        name,
+        full_text,
+        short_text,
+        color: match state {
+            ThresholdState::Normal => None,
+            ThresholdState::Warning => Some(I3BAR_WARNING_COLOR),
+            ThresholdState::Critical => Some(I3BAR_CRITICAL_COLOR),
+        },
+        urgent: state == ThresholdState::Critical,
+    }
+}
+
+fn class(state: ThresholdState) -> &'static str {
+    match state {
+        ThresholdState::Normal => "normal",
+        ThresholdState::Warning => "warning",
+        ThresholdState::Critical => "critical",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::Template;
+    use serde_json::{json, Value};
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn stats() -> Snapshot {
+        Snapshot {
+            cpu_usage: 42.4,
+            cpu_celsius: Some(96.0),
+            gpu_celsius: Some(61.2),
+            memory_used_bytes: 4 * GIB,
+            memory_total_bytes: 16 * GIB,
+            download_bps: 1_300_000,
+            upload_bps: 125_000,
+            ..Snapshot::default()
+        }
+    }
+
+    fn parse(line: &str) -> Value {
+        serde_json::from_str(line).unwrap()
+    }
+
+    #[test]
+    fn waybar_line_carries_hottest_state_and_percentage() {
+        let line = parse(&waybar(&stats(), &Config::default()));
+
+        assert_eq!(line["text"], segments::plain_line(&stats(), &Config::default()));
+        assert_eq!(line["class"], "critical");
+        assert_eq!(line["percentage"], 42);
+        assert!(line["tooltip"].as_str().unwrap().starts_with("CPU: "));
+        assert_eq!(line["tooltip"].as_str().unwrap().lines().count(), 4);
+    }
+
+    #[test]
+    fn waybar_percentage_falls_back_to_memory_then_none() {
+        let mut config = Config::default();
+        config.monitors.cpu_usage = false;
+        config.monitors.cpu_temperature = false;
+        let line = parse(&waybar(&stats(), &config));
+        assert_eq!(line["percentage"], 25);
+        assert_eq!(line["class"], "normal");
+
+        config.monitors.memory = false;
+        let line = parse(&waybar(&stats(), &config));
+        assert_eq!(line.get("percentage"), None);
+    }
+
+    #[test]
+    fn i3bar_has_one_block_per_segment() {
+        let blocks = parse(&i3bar(&stats(), &Config::default()));
+        let names: Vec<_> = blocks.as_array().unwrap().iter().map(|block| &block["name"]).collect();
+
+        assert_eq!(names, [&json!("cpu"), &json!("gpu"), &json!("memory"), &json!("network")]);
+        assert_eq!(blocks[0]["color"], I3BAR_CRITICAL_COLOR);
+        assert_eq!(blocks[0]["urgent"], true);
+        assert_eq!(blocks[1].get("color"), None);
+        assert_eq!(blocks[1]["urgent"], false);
+        assert!(!blocks[3]["short_text"].as_str().unwrap().contains('\n'));
+    }
+
+    #[test]
+    fn i3bar_custom_format_is_one_block() {
+        let config = Config {
+            format: Some(Template::parse("{cpu.usage} {gpu.temp}").unwrap()),
+            ..Config::default()
+        };
+        let blocks = parse(&i3bar(&stats(), &config));
+
+        assert_eq!(blocks.as_array().unwrap().len(), 1);
+        assert_eq!(blocks[0]["name"], "systemstats");
+        assert_eq!(blocks[0]["full_text"], "42% 61°C");
+        assert_eq!(blocks[0]["short_text"], "42% 61°C");
+        assert_eq!(blocks[0]["color"], I3BAR_CRITICAL_COLOR);
+    }
+
+    #[test]
+    fn warning_state_is_coloured_but_not_urgent() {
+        let stats = Snapshot { cpu_celsius: Some(85.0), ..stats() };
+        let blocks = parse(&i3bar(&stats, &Config::default()));
+
+        assert_eq!(blocks[0]["color"], I3BAR_WARNING_COLOR);
+        assert_eq!(blocks[0]["urgent"], false);
+        assert_eq!(parse(&waybar(&stats, &Config::default()))["class"], "warning");
+    }
+}
@@ -0,0 +1,72 @@
+//! Headless mode: prints the panel line to stdout for terminals, status bars and scripts
+
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bar;
+use crate::config::Config;
+use crate::monitors::MonitorStats;
+use crate::segments;
+use crate::sysfs::Root;
+
+/// What each printed line looks like
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Output {
+    /// The panel text
+    #[default]
+    Plain,
+    /// JSON for a Waybar custom module with `return-type = "json"`
+    Waybar,
+    /// The i3bar protocol, also understood by swaybar
+    I3bar,
+}
+
+/// Gap between the two samples `--once` takes, since usage and rates are differences
+const ONCE_SAMPLE_GAP: Duration = Duration::from_millis(500);
+
+/// Print a line every time a monitor is sampled, or just one with `once`
+pub fn run(config: &Config, output: Output, once: bool) -> io::Result<()> {
+    let mut monitors = MonitorStats::new(config, &Root::from_env());
+    let mut stdout = io::stdout().lock();
+
+    if output == Output::I3bar {
+        writeln!(stdout, "{}", bar::I3BAR_HEADER)?;
+    }
+
+    if once {
+        thread::sleep(ONCE_SAMPLE_GAP);
+        monitors.update(config, Instant::now());
+        return print(&mut stdout, &monitors, config, output, true);
+    }
+
+    let mut first = true;
+    loop {
+        thread::sleep(monitors.tick(config));
+        if monitors.update(config, Instant::now()) {
+            print(&mut stdout, &monitors, config, output, first)?;
+            first = false;
+        }
+    }
+}
+
+fn print(
+    out: &mut impl Write,
+    monitors: &MonitorStats,
+    config: &Config,
+    output: Output,
+    first: bool,
+) -> io::Result<()> {
+    let snapshot = monitors.snapshot();
+    match output {
+        Output::Plain => writeln!(out, "{}", segments::plain_line(&snapshot, config))?,
+        Output::Waybar => writeln!(out, "{}", bar::waybar(&snapshot, config))?,
+        Output::I3bar => {
+            // Status lines after the first are elements of the array opened by the header
+            let separator = if first { "" } else { "," };
+            writeln!(out, "{}{}", separator, bar::i3bar(&snapshot, config))?
+        }
+    }
+    // Status bars read line by line, so don't let a pipe buffer hold output back
+    out.flush()
+}
@@ -0,0 +1,247 @@
+//! Background sampling
+//!
+//! The monitors live on a dedicated thread so sysfs walks and external tools
+//! never block the panel. Each tick the thread publishes an immutable
+//! [`Snapshot`], and the applet sends config changes back through a [`Handle`].
+//! The thread can also save what it samples, play a recording back in place
+//! of the sensors, keep long-running logs, and serve its latest snapshot to
+//! Prometheus, D-Bus and the query socket.
+
+use cosmic::iced::futures::channel::mpsc;
+use cosmic::iced::futures::{SinkExt, StreamExt};
+use cosmic::iced::{stream, Subscription};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self as sync_mpsc, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Instant, SystemTime};
+
+use crate::adaptive::{environment, Pace};
+use crate::config::Config;
+use crate::dbus;
+use crate::exporter::{self, Exporter, Latest};
+use crate::history::History;
+use crate::monitors::{MonitorStats, Snapshot};
+use crate::recording::{Record, Recorder, Replay};
+use crate::sample_log::SampleLog;
+use crate::socket;
+use crate::sysfs::Root;
+
+/// Where snapshots come from and whether they are saved
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Play back this recording instead of reading sensors
+    pub replay: Option<PathBuf>,
+    /// Save every snapshot to this file
+    pub record: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The collector is running and waiting for its first config
+    Started(Handle),
+    Sampled(Snapshot),
+}
+
+/// Sends config changes to the collector thread
+#[derive(Debug, Clone)]
+pub struct Handle(sync_mpsc::Sender<Config>);
+
+impl Handle {
+    pub fn reconfigure(&self, config: &Config) {
+        if self.0.send(config.clone()).is_err() {
+            log::warn!("Collector has stopped; readings will no longer update");
+        }
+    }
+}
+
+struct Collector;
+
+pub fn subscription(options: Options) -> Subscription<Event> {
+    Subscription::run_with_id(
+        std::any::TypeId::of::<Collector>(),
+        stream::channel(1, |mut output| async move {
+            let (configs, config_rx) = sync_mpsc::channel();
+            let (snapshot_tx, mut snapshots) = mpsc::channel(1);
+
+            let spawned = thread::Builder::new()
+                .name("collector".into())
+                .spawn(move || match options.replay {
+                    Some(path) => replay(&path, config_rx, snapshot_tx),
+                    None => run(config_rx, snapshot_tx, options.record.as_deref()),
+                });
+            if let Err(e) = spawned {
+                log::error!("Failed to start collector: {}", e);
+                return;
+            }
+
+            if output.send(Event::Started(Handle(configs))).await.is_err() {
+                return;
+            }
+
+            while let Some(snapshot) = snapshots.next().await {
+                if output.send(Event::Sampled(snapshot)).await.is_err() {
+                    break;
+                }
+            }
+        }),
+    )
+}
+
+/// Sample on the schedule until the applet goes away
+fn run(
+    configs: sync_mpsc::Receiver<Config>,
+    mut snapshots: mpsc::Sender<Snapshot>,
+    record: Option<&Path>,
+) {
+    let Ok(mut config) = configs.recv() else {
+        return;
+    };
+
+    let mut recorder = record.and_then(|path| match Recorder::create(path) {
+        Ok(recorder) => {
+            log::info!("Recording samples to {}", path.display());
+            Some(recorder)
+        }
+        Err(e) => {
+            log::error!("Failed to create recording {}: {}", path.display(), e);
+            None
+        }
+    });
+
+    let mut sample_log = SampleLog::new(Config::state_dir());
+
+    let latest = Latest::default();
+    let mut exporter: Option<Exporter> = None;
+    exporter::sync(&mut exporter, &config.exporter, &latest);
+
+    let history = Arc::new(Mutex::new(History::default()));
+
+    let mut service = match dbus::Service::start(history.clone()) {
+        Ok(service) => Some(service),
+        // Most likely another panel's applet already publishes
+        Err(e) => {
+            log::info!("Not publishing stats on D-Bus: {}", e);
+            None
+        }
+    };
+
+    let server = socket::path().and_then(|path| {
+        socket::Server::start(&path, history.clone())
+            .inspect_err(|e| log::info!("Not answering queries on {}: {}", path.display(), e))
+            .ok()
+    });
+
+    let root = Root::from_env();
+    let mut monitors = MonitorStats::new(&config, &root);
+    let environment = environment::watch(&root);
+    let mut pace = Pace::new(environment.clone());
+    let mut next = Instant::now() + monitors.tick(&config);
+
+    loop {
+        match configs.recv_timeout(next.saturating_duration_since(Instant::now())) {
+            Ok(new) => {
+                monitors.reconfigure(&config, &new);
+                exporter::sync(&mut exporter, &new.exporter, &latest);
+                config = new;
+                // A shorter interval shouldn't wait out the old one
+                next = next.min(Instant::now() + monitors.tick(&config));
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let now = Instant::now();
+                environment.poll_power_supplies(now);
+                if monitors.update(&config, now) {
+                    let snapshot = monitors.snapshot();
+                    monitors.set_pace(pace.observe(&config.adaptive, &snapshot));
+                    latest.publish(&snapshot, &config.monitors);
+
+                    let record = Record::new(snapshot.clone(), SystemTime::now());
+                    if let Ok(mut history) = history.lock() {
+                        history.push(record.clone());
+                    }
+                    if let Some(service) = &mut service {
+                        if let Err(e) = service.publish(&snapshot) {
+                            log::warn!("Failed to publish stats on D-Bus: {}", e);
+                        }
+                    }
+                    if let Some(server) = &server {
+                        server.publish(&record);
+                    }
+
+                    if let Some(writer) = &mut recorder {
+                        if let Err(e) = writer.write(&record) {
+                            log::error!("Failed to write recording, stopping it: {}", e);
+                            recorder = None;
+                        }
+                    }
+
+                    let written = sample_log.write(
+                        &config.sample_log,
+                        &config.monitors,
+                        &snapshot,
+                        SystemTime::now(),
+                    );
+                    if let Err(e) = written {
+                        log::error!(
+                            "Failed to write sample log, stopping it until its settings change: {}",
+                            e
+                        );
+                    }
+
+                    // A full channel means the UI hasn't caught up; it gets the next one
+                    let sent = snapshots.try_send(snapshot);
+                    if sent.is_err_and(|e| e.is_disconnected()) {
+                        return;
+                    }
+                }
+
+                // Skip ticks missed while sampling rather than bursting to catch up
+                next += monitors.tick(&config);
+                if next < now {
+                    next = now + monitors.tick(&config);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Send a recording's snapshots with their original spacing, then stop
+fn replay(path: &Path, configs: sync_mpsc::Receiver<Config>, mut snapshots: mpsc::Sender<Snapshot>) {
+    let records = match Replay::open(path) {
+        Ok(records) => records,
+        Err(e) => {
+            log::error!("Failed to open recording {}: {}", path.display(), e);
+            return;
+        }
+    };
+    log::info!("Replaying samples from {}", path.display());
+
+    let mut next = Instant::now();
+    for record in records {
+        let (delay, snapshot) = match record {
+            Ok(record) => record,
+            Err(e) => {
+                log::error!("Stopping replay of {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        // Config changes don't affect a recording, but a closed channel means the applet is gone
+        next += delay;
+        loop {
+            match configs.recv_timeout(next.saturating_duration_since(Instant::now())) {
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        let sent = snapshots.try_send(snapshot);
+        if sent.is_err_and(|e| e.is_disconnected()) {
+            return;
+        }
+    }
+
+    log::info!("Replay finished; the last sample stays on screen");
+}
@@ -0,0 +1,341 @@
+//! Upgrades for older settings layouts
+//!
+//! The settings store keeps each schema version in its own `v<N>` directory,
+//! one file of RON per key. When this release's directory doesn't exist yet,
+//! the newest older one is read, brought forward a version at a time and
+//! written as the current one. The old directory is never touched, so it
+//! doubles as the backup.
+//!
+//! `config.toml` carries a top-level `version`; a file without it predates
+//! the marker and counts as version 0. Its steps edit the parsed document
+//! rather than re-serializing the config, so comments and formatting survive
+//! an upgrade.
+
+use cosmic::cosmic_config::CosmicConfigEntry;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml_edit::{value, DocumentMut};
+
+use super::{Config, Issue};
+
+/// Schema version written by this release, shared with the settings store
+pub const CURRENT_VERSION: u64 = Config::VERSION;
+
+/// `MIGRATIONS[n]` takes a document from version `n` to `n + 1`
+const MIGRATIONS: &[fn(&mut DocumentMut)] = &[v0_to_v1];
+
+const _: () = assert!(MIGRATIONS.len() as u64 == CURRENT_VERSION);
+
+/// Raw RON text of each key in a settings store directory
+pub type Entries = BTreeMap<String, String>;
+
+/// `STORE_MIGRATIONS[n]` takes store entries from version `n + 1` to `n + 2`
+///
+/// The store started at version 1, so there is nothing to upgrade yet.
+const STORE_MIGRATIONS: &[fn(&mut Entries)] = &[];
+
+const _: () = assert!(STORE_MIGRATIONS.len() as u64 + 1 == CURRENT_VERSION);
+
+/// Every key added before versioning has a serde default, so only the marker is new
+fn v0_to_v1(_: &mut DocumentMut) {}
+
+/// Bring a document up to the current version, returning whether anything changed
+pub fn migrate(document: &mut DocumentMut) -> Result<bool, Issue> {
+    let from = match document.get("version") {
+        None => 0,
+        Some(item) => item
+            .as_integer()
+            .and_then(|version| u64::try_from(version).ok())
+            .ok_or_else(|| Issue::error("version", "must be a whole number; file left as is"))?,
+    };
+
+    if from > CURRENT_VERSION {
+        return Err(Issue::warning(
+            "version",
+            format!(
+                "file is for version {}, newer than this release ({}); settings it doesn't know are ignored",
+                from, CURRENT_VERSION
+            ),
+        ));
+    }
+
+    for (version, step) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        step(document);
+        document["version"] = value(version as i64 + 1);
+    }
+
+    Ok(from < CURRENT_VERSION)
+}
+
+/// Create this release's settings store from the newest older one, if any
+///
+/// `app_dir` is the directory holding the `v<N>` stores. Returns the version
+/// upgraded from, or `None` when the current store exists or there is no
+/// older one to start from.
+pub fn upgrade_store(app_dir: &Path) -> io::Result<Option<u64>> {
+    upgrade_store_with(app_dir, CURRENT_VERSION, STORE_MIGRATIONS)
+}
+
+/// This release's store entries, brought forward in memory from the newest
+/// older store when there are none yet; nothing is written
+pub fn read_store(app_dir: &Path) -> io::Result<Option<Entries>> {
+    let read = read_store_with(app_dir, CURRENT_VERSION, STORE_MIGRATIONS)?;
+    Ok(read.map(|(_, entries)| entries))
+}
+
+fn upgrade_store_with(app_dir: &Path, current: u64, steps: &[fn(&mut Entries)]) -> io::Result<Option<u64>> {
+    let target = store_dir(app_dir, current);
+    if target.exists() {
+        return Ok(None);
+    }
+    let Some((from, entries)) = read_store_with(app_dir, current, steps)? else {
+        return Ok(None);
+    };
+
+    // Written aside and renamed so an interrupted upgrade is retried rather than half-read
+    let staging = app_dir.join(format!("v{}.tmp", current));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    for (key, contents) in &entries {
+        fs::write(staging.join(key), contents)?;
+    }
+    fs::rename(&staging, &target)?;
+
+    Ok(Some(from))
+}
+
+/// The entries of store version `current` and the version they were read from
+fn read_store_with(
+    app_dir: &Path,
+    current: u64,
+    steps: &[fn(&mut Entries)],
+) -> io::Result<Option<(u64, Entries)>> {
+    let target = store_dir(app_dir, current);
+    if target.is_dir() {
+        return Ok(Some((current, read_entries(&target)?)));
+    }
+    let Some(from) = (1..current).rev().find(|&version| store_dir(app_dir, version).is_dir()) else {
+        return Ok(None);
+    };
+
+    let mut entries = read_entries(&store_dir(app_dir, from))?;
+    for step in &steps[(from - 1) as usize..] {
+        step(&mut entries);
+    }
+    Ok(Some((from, entries)))
+}
+
+fn store_dir(app_dir: &Path, version: u64) -> PathBuf {
+    app_dir.join(format!("v{}", version))
+}
+
+fn read_entries(dir: &Path) -> io::Result<Entries> {
+    let mut entries = Entries::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Ok(key) = entry.file_name().into_string() else {
+            continue;
+        };
+        // Left behind by interrupted atomic writes
+        if key.starts_with('.') || !entry.file_type()?.is_file() {
+            continue;
+        }
+        entries.insert(key, fs::read_to_string(entry.path())?);
+    }
+    Ok(entries)
+}
+
+/// Upgrade a config file in place, keeping the original alongside as `<name>.bak`
+///
+/// Returns the text to parse: the upgraded document, or the original when it
+/// was current already or couldn't be upgraded.
+pub fn upgrade_file(path: &Path, contents: String, issues: &mut Vec<Issue>) -> String {
+    let Some(upgraded) = upgrade_text(&contents, issues) else {
+        return contents;
+    };
+
+    match backup_and_write(path, &upgraded) {
+        Ok(backup) => log::info!(
+            "Upgraded {} to version {}; the original is at {}",
+            path.display(),
+            CURRENT_VERSION,
+            backup.display()
+        ),
+        Err(e) => log::warn!("Failed to upgrade {}: {}", path.display(), e),
+    }
+    upgraded
+}
+
+/// The upgraded text of a config file, or `None` when there's nothing to change
+pub fn upgrade_text(contents: &str, issues: &mut Vec<Issue>) -> Option<String> {
+    // Syntax errors are left for the parser to report
+    let mut document = contents.parse::<DocumentMut>().ok()?;
+
+    match migrate(&mut document) {
+        Ok(true) => Some(document.to_string()),
+        Ok(false) => None,
+        Err(issue) => {
+            issues.push(issue);
+            None
+        }
+    }
+}
+
+fn backup_and_write(path: &Path, contents: &str) -> io::Result<PathBuf> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
+
+    fs::copy(path, &backup)?;
+    fs::write(path, contents)?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::validate::Severity;
+
+    /// A config.toml from before the version marker
+    const V0_TOML: &str = "\
+# Sample every two seconds
+refresh_interval_ms = 2000
+
+[monitors]
+gpu_temperature = false # no discrete GPU
+";
+
+    #[test]
+    fn v0_file_is_upgraded_in_place_with_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, V0_TOML).unwrap();
+
+        let mut issues = Vec::new();
+        let upgraded = upgrade_file(&path, V0_TOML.to_string(), &mut issues);
+
+        assert!(issues.is_empty());
+        assert!(upgraded.contains("# Sample every two seconds"));
+        assert!(upgraded.contains("# no discrete GPU"));
+        assert!(upgraded.contains(&format!("version = {}", CURRENT_VERSION)));
+        assert_eq!(fs::read_to_string(&path).unwrap(), upgraded);
+        assert_eq!(fs::read_to_string(dir.path().join("config.toml.bak")).unwrap(), V0_TOML);
+
+        let (config, issues) = Config::load_from(&path).unwrap();
+        assert!(issues.is_empty());
+        assert_eq!(config.refresh_interval_ms, 2000);
+        assert!(!config.monitors.gpu_temperature);
+        assert!(config.monitors.cpu_usage);
+    }
+
+    #[test]
+    fn current_file_is_left_alone() {
+        let mut document: DocumentMut = format!("version = {}\n", CURRENT_VERSION).parse().unwrap();
+        assert_eq!(migrate(&mut document), Ok(false));
+    }
+
+    #[test]
+    fn newer_or_malformed_version_is_reported() {
+        let mut newer: DocumentMut = format!("version = {}\n", CURRENT_VERSION + 1).parse().unwrap();
+        let issue = migrate(&mut newer).unwrap_err();
+        assert_eq!(issue.severity, Severity::Warning);
+
+        let mut malformed: DocumentMut = "version = \"one\"\n".parse().unwrap();
+        assert_eq!(migrate(&mut malformed).unwrap_err().key, "version");
+    }
+
+    fn write_store(app_dir: &Path, version: u64, entries: &[(&str, &str)]) {
+        let dir = store_dir(app_dir, version);
+        fs::create_dir_all(&dir).unwrap();
+        for (key, contents) in entries {
+            fs::write(dir.join(key), contents).unwrap();
+        }
+    }
+
+    /// Pretend version 2 renamed `interval` and version 3 doubled it
+    const STEPS: &[fn(&mut Entries)] = &[
+        |entries| {
+            if let Some(interval) = entries.remove("interval") {
+                entries.insert("refresh_interval_ms".into(), interval);
+            }
+        },
+        |entries| {
+            if let Some(ms) = entries.get_mut("refresh_interval_ms") {
+                *ms = (ms.trim().parse::<u64>().unwrap() * 2).to_string();
+            }
+        },
+    ];
+
+    #[test]
+    fn store_is_upgraded_through_each_version() {
+        let app_dir = tempfile::tempdir().unwrap();
+        write_store(app_dir.path(), 1, &[("interval", "500"), ("units", "(fixed_width: true)")]);
+
+        assert_eq!(upgrade_store_with(app_dir.path(), 3, STEPS).unwrap(), Some(1));
+
+        let upgraded = read_entries(&store_dir(app_dir.path(), 3)).unwrap();
+        assert_eq!(upgraded["refresh_interval_ms"], "1000");
+        assert_eq!(upgraded["units"], "(fixed_width: true)");
+        assert!(!upgraded.contains_key("interval"));
+        // The old version stays as the backup
+        assert_eq!(read_entries(&store_dir(app_dir.path(), 1)).unwrap()["interval"], "500");
+    }
+
+    #[test]
+    fn store_upgrades_from_newest_older_version() {
+        let app_dir = tempfile::tempdir().unwrap();
+        write_store(app_dir.path(), 1, &[("interval", "500")]);
+        write_store(app_dir.path(), 2, &[("refresh_interval_ms", "700")]);
+        // Left by an earlier attempt that was interrupted
+        write_store(app_dir.path(), 3, &[]);
+        fs::rename(store_dir(app_dir.path(), 3), app_dir.path().join("v3.tmp")).unwrap();
+
+        assert_eq!(upgrade_store_with(app_dir.path(), 3, STEPS).unwrap(), Some(2));
+
+        let upgraded = read_entries(&store_dir(app_dir.path(), 3)).unwrap();
+        assert_eq!(upgraded.len(), 1);
+        assert_eq!(upgraded["refresh_interval_ms"], "1400");
+        assert!(!app_dir.path().join("v3.tmp").exists());
+    }
+
+    #[test]
+    fn store_upgrade_skips_current_or_missing_stores() {
+        let app_dir = tempfile::tempdir().unwrap();
+        assert_eq!(upgrade_store_with(app_dir.path(), 3, STEPS).unwrap(), None);
+
+        write_store(app_dir.path(), 1, &[("interval", "500")]);
+        write_store(app_dir.path(), 3, &[("refresh_interval_ms", "2000")]);
+        assert_eq!(upgrade_store_with(app_dir.path(), 3, STEPS).unwrap(), None);
+        assert_eq!(read_entries(&store_dir(app_dir.path(), 3)).unwrap()["refresh_interval_ms"], "2000");
+
+        assert_eq!(upgrade_store(app_dir.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn store_is_read_without_writing() {
+        let app_dir = tempfile::tempdir().unwrap();
+        assert_eq!(read_store_with(app_dir.path(), 3, STEPS).unwrap(), None);
+
+        write_store(app_dir.path(), 1, &[("interval", "500")]);
+        let (from, entries) = read_store_with(app_dir.path(), 3, STEPS).unwrap().unwrap();
+
+        assert_eq!(from, 1);
+        assert_eq!(entries["refresh_interval_ms"], "1000");
+        assert!(!store_dir(app_dir.path(), 3).exists());
+        assert!(!app_dir.path().join("v3.tmp").exists());
+    }
+
+    #[test]
+    fn text_is_upgraded_in_memory() {
+        let mut issues = Vec::new();
+        let upgraded = upgrade_text(V0_TOML, &mut issues).unwrap();
+
+        assert!(issues.is_empty());
+        assert!(upgraded.contains(&format!("version = {}", CURRENT_VERSION)));
+        assert_eq!(upgrade_text(&upgraded, &mut issues), None);
+    }
+}
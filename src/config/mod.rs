@@ -0,0 +1,786 @@
+use cosmic::cosmic_config::{self, cosmic_config_derive::CosmicConfigEntry, CosmicConfigEntry};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+
+use crate::app::ID;
+use crate::segments::SegmentKind;
+use crate::template::Template;
+
+pub mod migrate;
+pub mod validate;
+
+pub use validate::Issue;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, CosmicConfigEntry)]
+#[version = 1]
+pub struct Config {
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval_ms: u64,
+
+    /// Custom panel string, replacing the built-in layout when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<Template>,
+
+    #[serde(default)]
+    pub monitors: MonitorToggles,
+
+    #[serde(default)]
+    pub intervals: Intervals,
+
+    #[serde(default)]
+    pub adaptive: Adaptive,
+
+    #[serde(default)]
+    pub units: Units,
+
+    #[serde(default)]
+    pub display: Display,
+
+    #[serde(default)]
+    pub thresholds: Thresholds,
+
+    #[serde(default)]
+    pub alerts: Vec<AlertRule>,
+
+    #[serde(default)]
+    pub exporter: Exporter,
+
+    #[serde(default)]
+    pub sample_log: SampleLog,
+
+    #[serde(default)]
+    pub clicks: Clicks,
+
+    #[serde(default)]
+    pub pages: Pages,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(std::io::Error),
+    Parse(toml::de::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MonitorToggles {
+    #[serde(default = "default_true")]
+    pub cpu_usage: bool,
+
+    #[serde(default = "default_true")]
+    pub cpu_temperature: bool,
+
+    #[serde(default = "default_true")]
+    pub gpu_temperature: bool,
+
+    #[serde(default = "default_true")]
+    pub memory: bool,
+
+    #[serde(default = "default_true")]
+    pub network: bool,
+}
+
+/// Per-monitor sampling intervals in milliseconds; unset ones follow `refresh_interval_ms`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Intervals {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_ms: Option<u64>,
+
+    /// Covers both CPU and GPU temperature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature_ms: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_ms: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_ms: Option<u64>,
+}
+
+/// Slows sampling down when nobody needs fast updates
+///
+/// Each factor multiplies every interval while its condition holds; the
+/// largest applicable one wins and 1 turns that condition off.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Adaptive {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Running from a discharging battery
+    #[serde(default = "default_battery_factor")]
+    pub on_battery: u32,
+
+    /// The session is idle or the screen is locked
+    #[serde(default = "default_idle_factor")]
+    pub when_idle: u32,
+
+    /// Readings have barely moved for a while; reached gradually and dropped on the first change
+    #[serde(default = "default_stable_factor")]
+    pub when_stable: u32,
+}
+
+/// What clicking or scrolling on the panel does
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Clicks {
+    #[serde(default = "default_left_click")]
+    pub left: ClickAction,
+
+    #[serde(default)]
+    pub middle: ClickAction,
+
+    #[serde(default)]
+    pub right: ClickAction,
+
+    #[serde(default = "default_scroll_up")]
+    pub scroll_up: ClickAction,
+
+    #[serde(default = "default_scroll_down")]
+    pub scroll_down: ClickAction,
+
+    /// Overrides while the pointer is over one segment
+    #[serde(default, skip_serializing_if = "SegmentClicks::is_empty")]
+    pub cpu: SegmentClicks,
+
+    #[serde(default, skip_serializing_if = "SegmentClicks::is_empty")]
+    pub gpu: SegmentClicks,
+
+    #[serde(default, skip_serializing_if = "SegmentClicks::is_empty")]
+    pub memory: SegmentClicks,
+
+    #[serde(default, skip_serializing_if = "SegmentClicks::is_empty")]
+    pub network: SegmentClicks,
+}
+
+/// Per-segment actions; unset ones fall back to the panel-wide action
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct SegmentClicks {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left: Option<ClickAction>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub middle: Option<ClickAction>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right: Option<ClickAction>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scroll_up: Option<ClickAction>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scroll_down: Option<ClickAction>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Click {
+    Left,
+    Middle,
+    Right,
+    ScrollUp,
+    ScrollDown,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClickAction {
+    #[default]
+    None,
+    /// Open the settings popup
+    Settings,
+    /// Switch between full and abbreviated values
+    ToggleCompact,
+    /// Run a shell command
+    Command(String),
+    /// Run a shell command in a terminal
+    Terminal(String),
+    /// Show the next or previous group of segments when `[pages]` is set
+    NextPage,
+    PreviousPage,
+}
+
+/// Segments shown one group at a time, for panel slots too narrow for all of them
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Pages {
+    /// Segments on each page, e.g. `[["cpu", "gpu"], ["memory"]]`; empty shows all at once
+    #[serde(default)]
+    pub groups: Vec<Vec<SegmentKind>>,
+
+    /// Seconds each page stays up; 0 only changes pages on click or scroll
+    #[serde(default = "default_page_interval")]
+    pub interval_secs: u64,
+}
+
+/// Prometheus endpoint serving the latest readings
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Exporter {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Address to listen on; anything but loopback exposes the readings to the network
+    #[serde(default = "default_exporter_bind")]
+    pub bind: IpAddr,
+
+    #[serde(default = "default_exporter_port")]
+    pub port: u16,
+}
+
+/// Every sample appended to rotating files under `$XDG_STATE_HOME/systemstats`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SampleLog {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default)]
+    pub format: LogFormat,
+
+    /// Start a new file once the current one reaches this size
+    #[serde(default = "default_log_max_size")]
+    pub max_size_mb: u64,
+
+    /// Start a new file once the current one is this old
+    #[serde(default = "default_log_max_age")]
+    pub max_age_hours: u64,
+
+    /// Oldest files beyond this count are deleted
+    #[serde(default = "default_log_keep_files")]
+    pub keep_files: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Comma-separated with a header row
+    #[default]
+    Csv,
+    /// InfluxDB line protocol
+    Influx,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Units {
+    #[serde(default)]
+    pub temperature: TemperatureUnit,
+
+    #[serde(default)]
+    pub memory: MemoryUnit,
+
+    #[serde(default)]
+    pub network: NetworkUnit,
+
+    /// Pad values to a constant width so the panel doesn't jitter as digits change
+    #[serde(default)]
+    pub fixed_width: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Display {
+    /// Reserve room for each segment's widest value so neighbouring applets don't move
+    #[serde(default = "default_true")]
+    pub stable_width: bool,
+
+    /// Render values in the monospace font so digits line up
+    #[serde(default)]
+    pub monospace: bool,
+
+    #[serde(default)]
+    pub cpu: LabelStyle,
+
+    #[serde(default)]
+    pub gpu: LabelStyle,
+
+    #[serde(default)]
+    pub memory: LabelStyle,
+
+    #[serde(default)]
+    pub network: LabelStyle,
+}
+
+/// How a segment is introduced in the panel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelStyle {
+    /// "CPU:", "RAM:", ...
+    #[default]
+    Text,
+    /// Symbolic icon tinted by the threshold state
+    Icon,
+}
+
+/// Warning and critical levels used to colour segments
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Thresholds {
+    #[serde(default = "default_usage_threshold")]
+    pub cpu_usage: Threshold,
+
+    #[serde(default = "default_temperature_threshold")]
+    pub cpu_temperature: Threshold,
+
+    #[serde(default = "default_temperature_threshold")]
+    pub gpu_temperature: Threshold,
+
+    #[serde(default = "default_usage_threshold")]
+    pub memory_usage: Threshold,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Threshold {
+    pub warning: f32,
+    pub critical: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+/// Decimal (GB, powers of 1000) or binary (GiB, powers of 1024) prefixes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryUnit {
+    Si,
+    #[default]
+    Iec,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkUnit {
+    #[default]
+    Bits,
+    Bytes,
+}
+
+/// A threshold rule that raises a desktop notification once breached for long enough
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AlertRule {
+    pub metric: AlertMetric,
+
+    pub condition: AlertCondition,
+
+    pub threshold: f32,
+
+    /// How long the condition must hold before notifying
+    #[serde(default)]
+    pub for_secs: u64,
+
+    /// Minimum time between two notifications for this rule
+    #[serde(default = "default_alert_cooldown")]
+    pub cooldown_secs: u64,
+
+    /// Send a follow-up notification once the value is back to normal
+    #[serde(default = "default_true")]
+    pub notify_recovery: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    CpuUsage,
+    CpuTemperature,
+    GpuTemperature,
+    MemoryUsage,
+    NetworkDownload,
+    NetworkUpload,
+    /// Percent free on the fullest mounted disk
+    DiskFree,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertCondition {
+    Above,
+    Below,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            refresh_interval_ms: default_refresh_interval(),
+            format: None,
+            monitors: MonitorToggles::default(),
+            intervals: Intervals::default(),
+            adaptive: Adaptive::default(),
+            units: Units::default(),
+            display: Display::default(),
+            thresholds: Thresholds::default(),
+            alerts: Vec::new(),
+            exporter: Exporter::default(),
+            sample_log: SampleLog::default(),
+            clicks: Clicks::default(),
+            pages: Pages::default(),
+        }
+    }
+}
+
+impl Default for MonitorToggles {
+    fn default() -> Self {
+        Self {
+            cpu_usage: true,
+            cpu_temperature: true,
+            gpu_temperature: true,
+            memory: true,
+            network: true,
+        }
+    }
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            on_battery: default_battery_factor(),
+            when_idle: default_idle_factor(),
+            when_stable: default_stable_factor(),
+        }
+    }
+}
+
+impl Default for Exporter {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: default_exporter_bind(),
+            port: default_exporter_port(),
+        }
+    }
+}
+
+impl Default for SampleLog {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            format: LogFormat::default(),
+            max_size_mb: default_log_max_size(),
+            max_age_hours: default_log_max_age(),
+            keep_files: default_log_keep_files(),
+        }
+    }
+}
+
+impl Default for Pages {
+    fn default() -> Self {
+        Self {
+            groups: Vec::new(),
+            interval_secs: default_page_interval(),
+        }
+    }
+}
+
+impl Default for Clicks {
+    fn default() -> Self {
+        Self {
+            left: default_left_click(),
+            middle: ClickAction::None,
+            right: ClickAction::None,
+            scroll_up: default_scroll_up(),
+            scroll_down: default_scroll_down(),
+            cpu: SegmentClicks::default(),
+            gpu: SegmentClicks::default(),
+            memory: SegmentClicks::default(),
+            network: SegmentClicks::default(),
+        }
+    }
+}
+
+impl Clicks {
+    /// The action for `click`, preferring the override of the segment under the pointer
+    pub fn action(&self, click: Click, segment: Option<SegmentKind>) -> &ClickAction {
+        let over = segment.and_then(|kind| self.segment(kind).get(click));
+        over.unwrap_or(match click {
+            Click::Left => &self.left,
+            Click::Middle => &self.middle,
+            Click::Right => &self.right,
+            Click::ScrollUp => &self.scroll_up,
+            Click::ScrollDown => &self.scroll_down,
+        })
+    }
+
+    pub fn segment(&self, kind: SegmentKind) -> &SegmentClicks {
+        match kind {
+            SegmentKind::Cpu => &self.cpu,
+            SegmentKind::Gpu => &self.gpu,
+            SegmentKind::Memory => &self.memory,
+            SegmentKind::Network => &self.network,
+        }
+    }
+}
+
+impl SegmentClicks {
+    pub fn get(&self, click: Click) -> Option<&ClickAction> {
+        match click {
+            Click::Left => self.left.as_ref(),
+            Click::Middle => self.middle.as_ref(),
+            Click::Right => self.right.as_ref(),
+            Click::ScrollUp => self.scroll_up.as_ref(),
+            Click::ScrollDown => self.scroll_down.as_ref(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self {
+            stable_width: true,
+            monospace: false,
+            cpu: LabelStyle::default(),
+            gpu: LabelStyle::default(),
+            memory: LabelStyle::default(),
+            network: LabelStyle::default(),
+        }
+    }
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            cpu_usage: default_usage_threshold(),
+            cpu_temperature: default_temperature_threshold(),
+            gpu_temperature: default_temperature_threshold(),
+            memory_usage: default_usage_threshold(),
+        }
+    }
+}
+
+fn default_refresh_interval() -> u64 {
+    1000 // Milliseconds
+}
+
+fn default_battery_factor() -> u32 {
+    2
+}
+
+fn default_idle_factor() -> u32 {
+    4
+}
+
+fn default_stable_factor() -> u32 {
+    4
+}
+
+fn default_left_click() -> ClickAction {
+    ClickAction::Settings
+}
+
+fn default_scroll_up() -> ClickAction {
+    ClickAction::PreviousPage
+}
+
+fn default_scroll_down() -> ClickAction {
+    ClickAction::NextPage
+}
+
+fn default_page_interval() -> u64 {
+    5
+}
+
+fn default_exporter_bind() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::LOCALHOST)
+}
+
+fn default_exporter_port() -> u16 {
+    9777
+}
+
+fn default_log_max_size() -> u64 {
+    10
+}
+
+fn default_log_max_age() -> u64 {
+    24
+}
+
+fn default_log_keep_files() -> u32 {
+    10
+}
+
+fn default_usage_threshold() -> Threshold {
+    Threshold {
+        warning: 80.0, // Percent
+        critical: 95.0,
+    }
+}
+
+fn default_temperature_threshold() -> Threshold {
+    Threshold {
+        warning: 80.0, // Celsius
+        critical: 95.0,
+    }
+}
+
+fn default_alert_cooldown() -> u64 {
+    300 // Seconds
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(e) => write!(f, "Failed to read config file: {}", e),
+            ConfigError::Parse(e) => write!(f, "Failed to parse config file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Load settings from cosmic-config, importing `config.toml` the first time the store is created
+    pub fn load() -> (Option<cosmic_config::Config>, Self, Vec<Issue>) {
+        if let Some(app_dir) = Self::store_path().as_deref().and_then(Path::parent) {
+            match migrate::upgrade_store(app_dir) {
+                Ok(Some(from)) => log::info!(
+                    "Upgraded settings from version {} to {}; the old ones are kept in {}",
+                    from,
+                    Self::VERSION,
+                    app_dir.join(format!("v{}", from)).display()
+                ),
+                Ok(None) => {}
+                Err(e) => log::warn!("Failed to upgrade settings: {}", e),
+            }
+        }
+
+        let first_run = Self::store_path().is_some_and(|path| !path.exists());
+
+        let handler = match cosmic_config::Config::new(ID, Self::VERSION) {
+            Ok(handler) => handler,
+            Err(e) => {
+                log::warn!("Failed to open settings store: {}. Using defaults.", e);
+                return (None, Self::default(), Vec::new());
+            }
+        };
+
+        let (config, issues) = if first_run {
+            Self::seed(&handler)
+        } else {
+            match Self::get_entry(&handler) {
+                Ok(config) => config.checked(Vec::new()),
+                Err((errors, config)) => {
+                    // Keys added by a newer release are missing until written once
+                    if errors.iter().all(|e| !e.is_err()) {
+                        if let Err(e) = config.write_entry(&handler) {
+                            log::warn!("Failed to add new settings: {}", e);
+                        }
+                    }
+                    config.checked(errors)
+                }
+            }
+        };
+
+        for issue in &issues {
+            log::warn!("Config: {}", issue);
+        }
+
+        (Some(handler), config, issues)
+    }
+
+    /// Load settings without writing anything, for one-off commands like `--cli`
+    ///
+    /// Gives what `load` would: the settings store, brought forward from an
+    /// older version in memory if need be, or else `config.toml` or the
+    /// defaults. Nothing is created, imported or upgraded on disk.
+    pub fn load_read_only() -> (Self, Vec<Issue>) {
+        let app_dir = Self::store_path().and_then(|path| Some(path.parent()?.to_path_buf()));
+        match app_dir.as_deref().map(migrate::read_store) {
+            Some(Ok(Some(entries))) => return validate::parse_store(&entries),
+            Some(Err(e)) => {
+                let issue = Issue::error("", format!("Failed to read settings: {}. Using defaults.", e));
+                return (Self::default(), vec![issue]);
+            }
+            _ => {}
+        }
+
+        match Self::toml_path().filter(|path| path.exists()) {
+            Some(path) => Self::read_toml(&path).unwrap_or_else(|e| {
+                (Self::default(), vec![Issue::error("", format!("{}. Using defaults.", e))])
+            }),
+            None => (Self::default(), Vec::new()),
+        }
+    }
+
+    /// Read and parse a config file, upgrading older layouts and keeping whatever sections are valid
+    pub fn load_from(path: &Path) -> Result<(Self, Vec<Issue>), ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::Read)?;
+
+        let mut issues = Vec::new();
+        let contents = migrate::upgrade_file(path, contents, &mut issues);
+        let (config, parse_issues) = validate::parse_toml(&contents).map_err(ConfigError::Parse)?;
+
+        issues.extend(parse_issues);
+        Ok((config, issues))
+    }
+
+    /// Like `load_from`, but an older layout is only upgraded in memory
+    fn read_toml(path: &Path) -> Result<(Self, Vec<Issue>), ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::Read)?;
+
+        let mut issues = Vec::new();
+        let contents = migrate::upgrade_text(&contents, &mut issues).unwrap_or(contents);
+        let (config, parse_issues) = validate::parse_toml(&contents).map_err(ConfigError::Parse)?;
+
+        issues.extend(parse_issues);
+        Ok((config, issues))
+    }
+
+    /// Path of the hand-edited TOML file used before settings moved to cosmic-config
+    pub fn toml_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|mut path| {
+            path.push("systemstats");
+            path.push("config.toml");
+            path
+        })
+    }
+
+    /// Directory sample logs are written to
+    pub fn state_dir() -> Option<PathBuf> {
+        dirs::state_dir().map(|mut path| {
+            path.push("systemstats");
+            path
+        })
+    }
+
+    /// Directory cosmic-config keeps this schema version's settings in
+    fn store_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|mut path| {
+            path.push("cosmic");
+            path.push(ID);
+            path.push(format!("v{}", Self::VERSION));
+            path
+        })
+    }
+
+    /// Fill a new settings store from `config.toml` if there is one, or the defaults
+    ///
+    /// Every key is written, so the store always lists each option available.
+    fn seed(handler: &cosmic_config::Config) -> (Self, Vec<Issue>) {
+        let (config, issues) = match Self::toml_path().filter(|path| path.exists()) {
+            Some(path) => match Self::load_from(&path) {
+                Ok(loaded) => {
+                    log::info!("Imported settings from {}; the file is no longer read", path.display());
+                    loaded
+                }
+                Err(e) => {
+                    log::warn!("{}. Skipping import.", e);
+                    (Self::default(), vec![Issue::error("", format!("{}. Nothing was imported.", e))])
+                }
+            },
+            None => (Self::default(), Vec::new()),
+        };
+
+        if let Err(e) = config.write_entry(handler) {
+            log::warn!("Failed to save settings: {}", e);
+        }
+        (config, issues)
+    }
+}
@@ -0,0 +1,746 @@
+//! Config validation and lenient TOML parsing
+//!
+//! Anything the types can't express (ranges, ordering) is checked here. Bad
+//! values are replaced with their defaults and reported as issues rather
+//! than discarding the whole config.
+
+use cosmic::cosmic_config;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use toml_edit::{ImDocument, Item};
+
+use super::migrate::Entries;
+use super::{ClickAction, Config};
+
+/// Shortest refresh interval accepted, shared with the settings slider
+pub const REFRESH_INTERVAL_MIN_MS: u64 = 100;
+const REFRESH_INTERVAL_MAX_MS: u64 = 60_000;
+
+/// Most an adaptive factor may stretch the configured intervals
+const ADAPTIVE_FACTOR_MAX: u32 = 16;
+
+/// Longest an alert condition may be required to hold
+const ALERT_DURATION_MAX_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// One-based position in the TOML file, or in the key's file of the settings store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Dotted path of the offending key, empty when it isn't tied to one
+    pub key: String,
+    pub location: Option<Location>,
+    pub message: String,
+}
+
+impl Issue {
+    pub fn warning(key: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            key: key.into(),
+            location: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn error(key: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            key: key.into(),
+            location: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(location) = self.location {
+            write!(f, "line {}, column {}: ", location.line, location.column)?;
+        }
+        if !self.key.is_empty() {
+            write!(f, "`{}`: ", self.key)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Config {
+    /// Check values the types can't, resetting out-of-range ones to their defaults
+    pub fn validate(&mut self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let defaults = Config::default();
+
+        if !(REFRESH_INTERVAL_MIN_MS..=REFRESH_INTERVAL_MAX_MS).contains(&self.refresh_interval_ms) {
+            issues.push(Issue::error(
+                "refresh_interval_ms",
+                format!(
+                    "{} ms is outside {}–{} ms; using {} ms",
+                    self.refresh_interval_ms,
+                    REFRESH_INTERVAL_MIN_MS,
+                    REFRESH_INTERVAL_MAX_MS,
+                    defaults.refresh_interval_ms
+                ),
+            ));
+            self.refresh_interval_ms = defaults.refresh_interval_ms;
+        }
+
+        let intervals = [
+            ("intervals.cpu_ms", &mut self.intervals.cpu_ms),
+            ("intervals.temperature_ms", &mut self.intervals.temperature_ms),
+            ("intervals.memory_ms", &mut self.intervals.memory_ms),
+            ("intervals.network_ms", &mut self.intervals.network_ms),
+        ];
+        for (key, interval) in intervals {
+            if let Some(ms) = interval.filter(|ms| !(REFRESH_INTERVAL_MIN_MS..=REFRESH_INTERVAL_MAX_MS).contains(ms)) {
+                issues.push(Issue::error(
+                    key,
+                    format!(
+                        "{} ms is outside {}–{} ms; using refresh_interval_ms",
+                        ms, REFRESH_INTERVAL_MIN_MS, REFRESH_INTERVAL_MAX_MS
+                    ),
+                ));
+                *interval = None;
+            }
+        }
+
+        let factors = [
+            ("adaptive.on_battery", &mut self.adaptive.on_battery, defaults.adaptive.on_battery),
+            ("adaptive.when_idle", &mut self.adaptive.when_idle, defaults.adaptive.when_idle),
+            ("adaptive.when_stable", &mut self.adaptive.when_stable, defaults.adaptive.when_stable),
+        ];
+        for (key, factor, default) in factors {
+            if !(1..=ADAPTIVE_FACTOR_MAX).contains(factor) {
+                issues.push(Issue::error(
+                    key,
+                    format!("{} is outside 1–{}; using {}", factor, ADAPTIVE_FACTOR_MAX, default),
+                ));
+                *factor = default;
+            }
+        }
+
+        let thresholds = [
+            ("thresholds.cpu_usage", &mut self.thresholds.cpu_usage, defaults.thresholds.cpu_usage),
+            ("thresholds.cpu_temperature", &mut self.thresholds.cpu_temperature, defaults.thresholds.cpu_temperature),
+            ("thresholds.gpu_temperature", &mut self.thresholds.gpu_temperature, defaults.thresholds.gpu_temperature),
+            ("thresholds.memory_usage", &mut self.thresholds.memory_usage, defaults.thresholds.memory_usage),
+        ];
+        for (key, threshold, default) in thresholds {
+            let valid = threshold.warning.is_finite()
+                && threshold.critical.is_finite()
+                && threshold.warning <= threshold.critical;
+
+            if !valid {
+                issues.push(Issue::error(
+                    key,
+                    format!(
+                        "warning ({}) must be a number no higher than critical ({}); using {} / {}",
+                        threshold.warning, threshold.critical, default.warning, default.critical
+                    ),
+                ));
+                *threshold = default;
+            }
+        }
+
+        if self.exporter.port == 0 {
+            issues.push(Issue::error(
+                "exporter.port",
+                format!("must be between 1 and 65535; using {}", defaults.exporter.port),
+            ));
+            self.exporter.port = defaults.exporter.port;
+        }
+
+        let log = &mut self.sample_log;
+        let limits = [
+            ("sample_log.max_size_mb", &mut log.max_size_mb, defaults.sample_log.max_size_mb),
+            ("sample_log.max_age_hours", &mut log.max_age_hours, defaults.sample_log.max_age_hours),
+        ];
+        for (key, limit, default) in limits {
+            if *limit == 0 {
+                issues.push(Issue::error(key, format!("must be at least 1; using {}", default)));
+                *limit = default;
+            }
+        }
+        if log.keep_files == 0 {
+            issues.push(Issue::error(
+                "sample_log.keep_files",
+                format!("must be at least 1; using {}", defaults.sample_log.keep_files),
+            ));
+            log.keep_files = defaults.sample_log.keep_files;
+        }
+
+        let custom_format = self.format.is_some();
+        let clicks = &mut self.clicks;
+        let buttons = [
+            ("clicks.left", &mut clicks.left),
+            ("clicks.middle", &mut clicks.middle),
+            ("clicks.right", &mut clicks.right),
+            ("clicks.scroll_up", &mut clicks.scroll_up),
+            ("clicks.scroll_down", &mut clicks.scroll_down),
+        ];
+        for (key, action) in buttons {
+            check_click_action(key.to_string(), action, &mut issues);
+        }
+        let segments = [
+            ("cpu", &mut clicks.cpu),
+            ("gpu", &mut clicks.gpu),
+            ("memory", &mut clicks.memory),
+            ("network", &mut clicks.network),
+        ];
+        for (segment, overrides) in segments {
+            // Kept as they are, for when the format is cleared again
+            if custom_format && !overrides.is_empty() {
+                issues.push(Issue::warning(
+                    format!("clicks.{}", segment),
+                    "a custom `format` has no segments to click; the [clicks] buttons apply",
+                ));
+            }
+            let buttons = [
+                ("left", &mut overrides.left),
+                ("middle", &mut overrides.middle),
+                ("right", &mut overrides.right),
+                ("scroll_up", &mut overrides.scroll_up),
+                ("scroll_down", &mut overrides.scroll_down),
+            ];
+            for (button, action) in buttons {
+                if let Some(action) = action {
+                    check_click_action(format!("clicks.{}.{}", segment, button), action, &mut issues);
+                }
+            }
+        }
+
+        if self.pages.groups.iter().any(Vec::is_empty) {
+            issues.push(Issue::warning("pages.groups", "empty pages are skipped"));
+            self.pages.groups.retain(|group| !group.is_empty());
+        }
+        if custom_format && !self.pages.groups.is_empty() {
+            issues.push(Issue::warning(
+                "pages.groups",
+                "a custom `format` is shown whole and can't be split into pages; pages ignored",
+            ));
+            self.pages.groups.clear();
+        }
+
+        let mut index = 0;
+        self.alerts.retain(|rule| {
+            let key = format!("alerts.{}", index);
+            index += 1;
+
+            if !rule.threshold.is_finite() {
+                issues.push(Issue::error(key, "threshold must be a number; rule ignored"));
+                false
+            } else if rule.for_secs > ALERT_DURATION_MAX_SECS {
+                issues.push(Issue::error(
+                    key,
+                    format!(
+                        "for_secs ({}) is longer than a day; rule ignored",
+                        rule.for_secs
+                    ),
+                ));
+                false
+            } else {
+                true
+            }
+        });
+
+        issues
+    }
+
+    /// Validate settings read from cosmic-config, turning read errors into issues
+    pub fn checked(mut self, errors: Vec<cosmic_config::Error>) -> (Self, Vec<Issue>) {
+        let store = Self::store_path().filter(|path| path.is_dir());
+
+        let mut issues: Vec<Issue> = errors
+            .into_iter()
+            .filter(cosmic_config::Error::is_err)
+            // The store check below reports these again with their key and position
+            .filter(|e| {
+                store.is_none()
+                    || !matches!(e, cosmic_config::Error::Ron(_) | cosmic_config::Error::RonSpanned(_))
+            })
+            .map(|e| Issue::error("", e.to_string()))
+            .collect();
+
+        if let Some(store) = &store {
+            issues.extend(check_store(store));
+        }
+        issues.extend(self.validate());
+        (self, issues)
+    }
+}
+
+/// A command with nothing to run would fail on every click
+fn check_click_action(key: String, action: &mut ClickAction, issues: &mut Vec<Issue>) {
+    if let ClickAction::Command(command) | ClickAction::Terminal(command) = action {
+        if command.trim().is_empty() {
+            issues.push(Issue::error(key, "command is empty; the click does nothing"));
+            *action = ClickAction::None;
+        }
+    }
+}
+
+/// Parse a TOML config, keeping every top-level section that deserializes
+///
+/// Only syntax errors fail outright. Sections with bad values fall back to
+/// their defaults and unknown keys are ignored, each reported with its
+/// position in the file.
+pub fn parse_toml(contents: &str) -> Result<(Config, Vec<Issue>), toml::de::Error> {
+    let mut table: toml::Table = toml::from_str(contents)?;
+    // Only used to pick migrations, which have already run
+    table.remove("version");
+    let document = ImDocument::parse(contents).ok();
+    let locate = |key: &str| document.as_ref().and_then(|document| locate(document, contents, key));
+
+    let mut issues = Vec::new();
+    let mut valid = toml::Table::new();
+
+    for (key, value) in table {
+        let mut candidate = valid.clone();
+        candidate.insert(key.clone(), value.clone());
+
+        match Config::deserialize(toml::Value::Table(candidate)) {
+            Ok(_) => {
+                valid.insert(key, value);
+            }
+            Err(e) => {
+                let mut issue = Issue::error(
+                    key.as_str(),
+                    format!("{}; using the default instead", e.message().trim_end()),
+                );
+                issue.location = locate(&key);
+                issues.push(issue);
+            }
+        }
+    }
+
+    let mut unknown = Vec::new();
+    let mut config: Config = serde_ignored::deserialize(toml::Value::Table(valid), |path| {
+        unknown.push(path.to_string());
+    })?;
+
+    for key in unknown {
+        let mut issue = Issue::warning(key.as_str(), "unknown key, ignored");
+        issue.location = locate(&key);
+        issues.push(issue);
+    }
+
+    for mut issue in config.validate() {
+        issue.location = locate(&issue.key);
+        issues.push(issue);
+    }
+
+    Ok((config, issues))
+}
+
+/// Check each file of a cosmic-config store the way `parse_toml` checks a file
+///
+/// cosmic-config skips files it has no key for and its parse errors name
+/// neither the key nor where in it the mistake is. Each file is read as a
+/// one-field config so unknown keys, including nested ones, are caught
+/// too; positions are relative to that file.
+pub fn check_store(dir: &Path) -> Vec<Issue> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut keys: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        // Left behind by interrupted atomic writes
+        .filter(|key| !key.starts_with('.'))
+        .collect();
+    keys.sort();
+
+    let mut issues = Vec::new();
+    for key in keys {
+        match fs::read_to_string(dir.join(&key)) {
+            Ok(contents) => {
+                check_store_key(&key, &contents, &mut issues);
+            }
+            Err(e) => issues.push(Issue::error(key, format!("{}; using the default instead", e))),
+        }
+    }
+    issues
+}
+
+/// Build a config from store entries without going through cosmic-config
+///
+/// Opening the store with cosmic-config creates it, so this is the way to
+/// read settings where nothing may be written. Keys that don't parse fall
+/// back to their defaults, as they do when cosmic-config loads them.
+pub fn parse_store(entries: &Entries) -> (Config, Vec<Issue>) {
+    let mut issues = Vec::new();
+    let mut source = String::from("(");
+    for (key, contents) in entries {
+        if check_store_key(key, contents, &mut issues) {
+            source.push_str(&format!("{}: {}\n,", key, contents));
+        }
+    }
+    source.push(')');
+
+    let mut config = match ron::from_str::<Config>(&source) {
+        Ok(config) => config,
+        Err(e) => {
+            issues.push(Issue::error("", format!("{}; using the defaults", e)));
+            Config::default()
+        }
+    };
+    issues.extend(config.validate());
+    (config, issues)
+}
+
+/// Check one store file, returning whether its value can be used
+fn check_store_key(key: &str, contents: &str, issues: &mut Vec<Issue>) -> bool {
+    let prefix = format!("({}: ", key);
+    let source = format!("{}{}\n)", prefix, contents);
+
+    let mut unknown = Vec::new();
+    let result = ron::Deserializer::from_str(&source).and_then(|mut deserializer| {
+        serde_ignored::deserialize(&mut deserializer, |path| unknown.push(path.to_string()))
+            .and_then(|_: Config| deserializer.end())
+            .map_err(|e| deserializer.span_error(e))
+    });
+
+    match result {
+        Ok(()) => {
+            for path in unknown {
+                issues.push(Issue::warning(path, "unknown key, ignored"));
+            }
+            true
+        }
+        Err(e) => {
+            let position = e.span.start;
+            let mut issue = Issue::error(key, format!("{}; using the default instead", e.code));
+            // Undo the wrapping, which only shifts the first line
+            issue.location = Some(Location {
+                line: position.line,
+                column: match position.line {
+                    1 => position.col.saturating_sub(prefix.chars().count()).max(1),
+                    _ => position.col,
+                },
+            });
+            issues.push(issue);
+            false
+        }
+    }
+}
+
+/// Find where a dotted key path starts in the source
+fn locate(document: &ImDocument<&str>, contents: &str, key: &str) -> Option<Location> {
+    let mut item: &Item = document.as_item();
+    let mut span = None;
+
+    for segment in key.split('.') {
+        let (next, next_span) = match segment.parse::<usize>() {
+            Ok(index) => {
+                let next = item.get(index)?;
+                (next, next.span())
+            }
+            Err(_) => {
+                let (key, next) = item.as_table_like()?.get_key_value(segment)?;
+                (next, key.span())
+            }
+        };
+
+        item = next;
+        span = next_span.or(span);
+    }
+
+    let before = contents.get(..span?.start)?;
+    Some(Location {
+        line: before.matches('\n').count() + 1,
+        column: before.rsplit('\n').next().map_or(0, |line| line.chars().count()) + 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AlertCondition, AlertMetric, AlertRule, Threshold};
+    use crate::segments::SegmentKind;
+    use crate::template::Template;
+
+    fn keys(issues: &[Issue]) -> Vec<&str> {
+        issues.iter().map(|issue| issue.key.as_str()).collect()
+    }
+
+    fn alert(threshold: f32, for_secs: u64) -> AlertRule {
+        AlertRule {
+            metric: AlertMetric::CpuUsage,
+            condition: AlertCondition::Above,
+            threshold,
+            for_secs,
+            cooldown_secs: 300,
+            notify_recovery: true,
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(Config::default().validate().is_empty());
+    }
+
+    #[test]
+    fn refresh_interval_out_of_range_uses_default() {
+        for ms in [REFRESH_INTERVAL_MIN_MS - 1, REFRESH_INTERVAL_MAX_MS + 1] {
+            let mut config = Config { refresh_interval_ms: ms, ..Config::default() };
+            let issues = config.validate();
+
+            assert_eq!(keys(&issues), ["refresh_interval_ms"]);
+            assert_eq!(issues[0].severity, Severity::Error);
+            assert_eq!(config.refresh_interval_ms, Config::default().refresh_interval_ms);
+        }
+    }
+
+    #[test]
+    fn source_interval_out_of_range_follows_refresh_interval() {
+        let mut config = Config::default();
+        config.intervals.cpu_ms = Some(50);
+        config.intervals.network_ms = Some(REFRESH_INTERVAL_MAX_MS + 1);
+        config.intervals.memory_ms = Some(2000);
+
+        let issues = config.validate();
+
+        assert_eq!(keys(&issues), ["intervals.cpu_ms", "intervals.network_ms"]);
+        assert_eq!(config.intervals.cpu_ms, None);
+        assert_eq!(config.intervals.network_ms, None);
+        assert_eq!(config.intervals.memory_ms, Some(2000));
+    }
+
+    #[test]
+    fn adaptive_factor_out_of_range_uses_default() {
+        let mut config = Config::default();
+        config.adaptive.on_battery = 0;
+        config.adaptive.when_stable = ADAPTIVE_FACTOR_MAX + 1;
+
+        let issues = config.validate();
+
+        assert_eq!(keys(&issues), ["adaptive.on_battery", "adaptive.when_stable"]);
+        assert_eq!(config.adaptive, Config::default().adaptive);
+    }
+
+    #[test]
+    fn inverted_or_missing_threshold_uses_default() {
+        let mut config = Config::default();
+        config.thresholds.cpu_usage = Threshold { warning: 90.0, critical: 70.0 };
+        config.thresholds.gpu_temperature.critical = f32::NAN;
+
+        let issues = config.validate();
+
+        assert_eq!(keys(&issues), ["thresholds.cpu_usage", "thresholds.gpu_temperature"]);
+        assert_eq!(config.thresholds, Config::default().thresholds);
+    }
+
+    #[test]
+    fn exporter_port_zero_uses_default() {
+        let mut config = Config::default();
+        config.exporter.port = 0;
+
+        assert_eq!(keys(&config.validate()), ["exporter.port"]);
+        assert_eq!(config.exporter.port, Config::default().exporter.port);
+    }
+
+    #[test]
+    fn zero_sample_log_limits_use_defaults() {
+        let mut config = Config::default();
+        config.sample_log.max_size_mb = 0;
+        config.sample_log.max_age_hours = 0;
+        config.sample_log.keep_files = 0;
+
+        let issues = config.validate();
+
+        assert_eq!(
+            keys(&issues),
+            ["sample_log.max_size_mb", "sample_log.max_age_hours", "sample_log.keep_files"]
+        );
+        assert_eq!(config.sample_log, Config::default().sample_log);
+    }
+
+    #[test]
+    fn empty_click_command_does_nothing() {
+        let mut config = Config::default();
+        config.clicks.middle = ClickAction::Command(" ".into());
+        config.clicks.memory.right = Some(ClickAction::Terminal(String::new()));
+        config.clicks.cpu.left = Some(ClickAction::Command("htop".into()));
+
+        let issues = config.validate();
+
+        assert_eq!(keys(&issues), ["clicks.middle", "clicks.memory.right"]);
+        assert_eq!(config.clicks.middle, ClickAction::None);
+        assert_eq!(config.clicks.memory.right, Some(ClickAction::None));
+        assert_eq!(config.clicks.cpu.left, Some(ClickAction::Command("htop".into())));
+    }
+
+    #[test]
+    fn segment_clicks_with_custom_format_warn() {
+        let mut config = Config::default();
+        config.clicks.gpu.left = Some(ClickAction::Settings);
+        assert_eq!(config.validate(), []);
+
+        config.format = Some(Template::parse("{gpu.temp}").unwrap());
+        let issues = config.validate();
+
+        assert_eq!(keys(&issues), ["clicks.gpu"]);
+        assert_eq!(issues[0].severity, Severity::Warning);
+        assert_eq!(config.clicks.gpu.left, Some(ClickAction::Settings));
+    }
+
+    #[test]
+    fn empty_pages_are_dropped() {
+        let mut config = Config::default();
+        config.pages.groups = vec![vec![SegmentKind::Cpu], Vec::new(), vec![SegmentKind::Memory]];
+
+        let issues = config.validate();
+
+        assert_eq!(keys(&issues), ["pages.groups"]);
+        assert_eq!(issues[0].severity, Severity::Warning);
+        assert_eq!(config.pages.groups, [[SegmentKind::Cpu], [SegmentKind::Memory]]);
+    }
+
+    #[test]
+    fn pages_with_custom_format_are_dropped() {
+        let mut config = Config::default();
+        config.pages.groups = vec![vec![SegmentKind::Cpu], vec![SegmentKind::Memory]];
+        config.format = Some(Template::parse("{cpu.usage}").unwrap());
+
+        let issues = config.validate();
+
+        assert_eq!(keys(&issues), ["pages.groups"]);
+        assert_eq!(issues[0].severity, Severity::Warning);
+        assert!(config.pages.groups.is_empty());
+    }
+
+    #[test]
+    fn invalid_alerts_are_dropped() {
+        let mut config = Config {
+            alerts: vec![
+                alert(90.0, 10),
+                alert(f32::NAN, 10),
+                alert(90.0, ALERT_DURATION_MAX_SECS + 1),
+            ],
+            ..Config::default()
+        };
+
+        let issues = config.validate();
+
+        assert_eq!(keys(&issues), ["alerts.1", "alerts.2"]);
+        assert_eq!(config.alerts, [alert(90.0, 10)]);
+    }
+
+    #[test]
+    fn toml_bad_section_falls_back_with_location() {
+        let contents = "refresh_interval_ms = 2000\n\n[units]\ntemperature = \"rankine\"\n";
+        let (config, issues) = parse_toml(contents).unwrap();
+
+        assert_eq!(config.refresh_interval_ms, 2000);
+        assert_eq!(config.units, Config::default().units);
+        assert_eq!(keys(&issues), ["units"]);
+        assert_eq!(issues[0].location, Some(Location { line: 3, column: 2 }));
+    }
+
+    #[test]
+    fn toml_unknown_keys_are_located() {
+        let contents = "refresh_intervall_ms = 2000\n\n[display]\nmonospace = true\ncolour = \"red\"\n";
+        let (config, issues) = parse_toml(contents).unwrap();
+
+        assert!(config.display.monospace);
+        assert_eq!(keys(&issues), ["display.colour", "refresh_intervall_ms"]);
+        assert!(issues.iter().all(|issue| issue.severity == Severity::Warning));
+        assert_eq!(issues[0].location, Some(Location { line: 5, column: 1 }));
+        assert_eq!(issues[1].location, Some(Location { line: 1, column: 1 }));
+    }
+
+    #[test]
+    fn toml_range_issues_are_located() {
+        let contents = "[exporter]\nenabled = true\nport = 0\n";
+        let (_, issues) = parse_toml(contents).unwrap();
+
+        assert_eq!(keys(&issues), ["exporter.port"]);
+        assert_eq!(issues[0].location, Some(Location { line: 3, column: 1 }));
+    }
+
+    #[test]
+    fn toml_syntax_error_fails() {
+        assert!(parse_toml("refresh_interval_ms = ").is_err());
+    }
+
+    fn store(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (key, contents) in files {
+            fs::write(dir.path().join(key), contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn store_entries_pass() {
+        let dir = store(&[
+            ("refresh_interval_ms", "2000"),
+            ("format", "Some(\"{cpu.usage}%\")"),
+            ("units", "(\n    temperature: fahrenheit,\n    fixed_width: true,\n)"),
+            (".refresh_interval_ms.tmp", "garbage"),
+        ]);
+
+        assert_eq!(check_store(dir.path()), []);
+    }
+
+    #[test]
+    fn store_unknown_keys_are_reported() {
+        let dir = store(&[
+            ("refresh_intervall_ms", "2000"),
+            ("display", "(\n    monospace: true,\n    colour: \"red\",\n)"),
+        ]);
+
+        let issues = check_store(dir.path());
+
+        assert_eq!(keys(&issues), ["display.colour", "refresh_intervall_ms"]);
+        assert!(issues.iter().all(|issue| issue.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn store_bad_values_are_located() {
+        let dir = store(&[
+            ("refresh_interval_ms", "\"fast\""),
+            ("units", "(\n    temperature: rankine,\n)"),
+        ]);
+
+        let issues = check_store(dir.path());
+
+        assert_eq!(keys(&issues), ["refresh_interval_ms", "units"]);
+        assert!(issues.iter().all(|issue| issue.severity == Severity::Error));
+        assert_eq!(issues[0].location.map(|location| location.line), Some(1));
+        assert_eq!(issues[1].location, Some(Location { line: 2, column: 18 }));
+    }
+
+    #[test]
+    fn store_entries_parse_into_config() {
+        let entries = [
+            ("refresh_interval_ms", "2000"),
+            ("units", "(\n    temperature: rankine,\n)"),
+            ("refresh_intervall_ms", "500"),
+            ("format", "Some(\"{cpu.usage}%\")"),
+        ];
+        let entries = entries.iter().map(|(key, contents)| (key.to_string(), contents.to_string()));
+
+        let (config, issues) = parse_store(&entries.collect());
+
+        assert_eq!(config.refresh_interval_ms, 2000);
+        assert!(config.format.is_some());
+        assert_eq!(config.units, Config::default().units);
+        assert_eq!(keys(&issues), ["refresh_intervall_ms", "units"]);
+    }
+}
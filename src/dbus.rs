@@ -0,0 +1,346 @@
+//! Live stats on the session bus
+//!
+//! Owns `io.github.rylan_x.SystemStats` so scripts and other applets can read
+//! the collector's numbers instead of sampling again. Current values are
+//! properties that signal when they change, and `GetHistory` reads the
+//! collector's shared [`History`](crate::history::History).
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use zbus::blocking::Connection;
+use zbus::fdo::{self, Properties};
+use zbus::names::InterfaceName;
+use zbus::zvariant::Value;
+
+use crate::history::Shared;
+use crate::monitors::Snapshot;
+
+pub const NAME: &str = "io.github.rylan_x.SystemStats";
+pub const PATH: &str = "/io/github/rylan_x/SystemStats";
+const INTERFACE: &str = "io.github.rylan_x.SystemStats";
+
+/// Reads one metric out of a snapshot, `None` when its sensor is missing
+type Metric = fn(&Snapshot) -> Option<f64>;
+
+/// Metrics `GetHistory` accepts, named as in `[[alerts]]`
+const METRICS: &[(&str, Metric)] = &[
+    ("cpu_usage", |stats| Some(stats.cpu_usage as f64)),
+    ("cpu_temperature", |stats| stats.cpu_celsius.map(f64::from)),
+    ("gpu_temperature", |stats| stats.gpu_celsius.map(f64::from)),
+    ("memory_usage", |stats| Some(stats.memory_usage_percent() as f64)),
+    ("network_download", |stats| Some(stats.download_bps as f64)),
+    ("network_upload", |stats| Some(stats.upload_bps as f64)),
+];
+
+/// The published interface
+struct Stats {
+    history: Shared,
+}
+
+impl Stats {
+    /// Read from the latest sample in place, or `default` before the first one
+    fn latest<T>(&self, default: T, read: impl FnOnce(&Snapshot) -> T) -> T {
+        let Ok(history) = self.history.lock() else {
+            return default;
+        };
+        history.latest().map_or(default, |record| read(&record.snapshot))
+    }
+}
+
+#[zbus::interface(name = "io.github.rylan_x.SystemStats")]
+impl Stats {
+    /// Every current value by name; sensors that weren't found are left out
+    fn get_snapshot(&self) -> HashMap<String, Value<'static>> {
+        let mut values = HashMap::new();
+        let Ok(history) = self.history.lock() else {
+            return values;
+        };
+        if let Some(record) = history.latest() {
+            let snapshot = &record.snapshot;
+            values.insert("timestamp_ms".to_string(), Value::from(record.time_ms));
+            for (name, value) in METRICS {
+                if let Some(value) = value(snapshot) {
+                    values.insert(name.to_string(), Value::from(value));
+                }
+            }
+            values.insert("memory_used_bytes".to_string(), Value::from(snapshot.memory_used_bytes));
+            values.insert("memory_total_bytes".to_string(), Value::from(snapshot.memory_total_bytes));
+            if let Some(interface) = &snapshot.interface {
+                values.insert("interface".to_string(), Value::from(interface.clone()));
+            }
+        }
+        values
+    }
+
+    /// `(unix time in ms, value)` pairs for one metric over the last `seconds`
+    fn get_history(&self, metric: &str, seconds: u32) -> fdo::Result<Vec<(u64, f64)>> {
+        let Some((_, value)) = METRICS.iter().find(|(name, _)| *name == metric) else {
+            let names: Vec<&str> = METRICS.iter().map(|(name, _)| *name).collect();
+            return Err(fdo::Error::InvalidArgs(format!(
+                "unknown metric '{}'; expected one of {}",
+                metric,
+                names.join(", ")
+            )));
+        };
+
+        let history = self
+            .history
+            .lock()
+            .map_err(|_| fdo::Error::Failed("history is unavailable".to_string()))?;
+        Ok(history
+            .since(u64::from(seconds))
+            .filter_map(|record| Some((record.time_ms, value(&record.snapshot)?)))
+            .collect())
+    }
+
+    /// Percent across all cores
+    #[zbus(property)]
+    fn cpu_usage(&self) -> f64 {
+        self.latest(f64::NAN, |stats| stats.cpu_usage as f64)
+    }
+
+    /// Celsius, NaN without a sensor
+    #[zbus(property)]
+    fn cpu_temperature(&self) -> f64 {
+        self.latest(f64::NAN, |stats| celsius(stats.cpu_celsius))
+    }
+
+    /// Celsius of the hottest GPU, NaN without one
+    #[zbus(property)]
+    fn gpu_temperature(&self) -> f64 {
+        self.latest(f64::NAN, |stats| celsius(stats.gpu_celsius))
+    }
+
+    #[zbus(property)]
+    fn memory_used(&self) -> u64 {
+        self.latest(0, |stats| stats.memory_used_bytes)
+    }
+
+    #[zbus(property)]
+    fn memory_total(&self) -> u64 {
+        self.latest(0, |stats| stats.memory_total_bytes)
+    }
+
+    /// Bytes per second
+    #[zbus(property)]
+    fn download_rate(&self) -> u64 {
+        self.latest(0, |stats| stats.download_bps)
+    }
+
+    /// Bytes per second
+    #[zbus(property)]
+    fn upload_rate(&self) -> u64 {
+        self.latest(0, |stats| stats.upload_bps)
+    }
+
+    /// Network interface the rates are measured on
+    #[zbus(property)]
+    fn interface(&self) -> String {
+        self.latest(String::new(), |stats| stats.interface.clone().unwrap_or_default())
+    }
+}
+
+/// D-Bus has no optional values, so a missing sensor reads as NaN
+fn celsius(value: Option<f32>) -> f64 {
+    value.map_or(f64::NAN, f64::from)
+}
+
+/// Every property's value, matching the getters, for working out which ones changed
+fn properties(stats: &Snapshot) -> [(&'static str, Value<'static>); 8] {
+    [
+        ("CpuUsage", Value::from(stats.cpu_usage as f64)),
+        ("CpuTemperature", Value::from(celsius(stats.cpu_celsius))),
+        ("GpuTemperature", Value::from(celsius(stats.gpu_celsius))),
+        ("MemoryUsed", Value::from(stats.memory_used_bytes)),
+        ("MemoryTotal", Value::from(stats.memory_total_bytes)),
+        ("DownloadRate", Value::from(stats.download_bps)),
+        ("UploadRate", Value::from(stats.upload_bps)),
+        ("Interface", Value::from(stats.interface.clone().unwrap_or_default())),
+    ]
+}
+
+/// A registered service, released when dropped
+pub struct Service {
+    connection: Connection,
+    /// Last published sample, to tell which properties changed
+    previous: Option<Snapshot>,
+}
+
+impl Service {
+    /// Publish on the session bus
+    pub fn start(history: Shared) -> zbus::Result<Self> {
+        Self::start_with(Connection::session()?, history)
+    }
+
+    /// Publish over a connection the caller already opened, on whichever bus it chose
+    pub fn start_with(connection: Connection, history: Shared) -> zbus::Result<Self> {
+        connection.object_server().at(PATH, Stats { history })?;
+        connection.request_name(NAME)?;
+        log::info!("Publishing stats on D-Bus as {}", NAME);
+        Ok(Self {
+            connection,
+            previous: None,
+        })
+    }
+
+    /// Signal the properties a new sample changed; it must already be in the history
+    pub fn publish(&mut self, snapshot: &Snapshot) -> zbus::Result<()> {
+        let before = self.previous.replace(snapshot.clone());
+        let before = before.as_ref().map(properties);
+        let changed: HashMap<&str, Value> = properties(snapshot)
+            .into_iter()
+            .enumerate()
+            // NaN never equals itself, so compare missing sensors by their bits
+            .filter(|(index, (_, value))| {
+                before.as_ref().is_none_or(|before| !same(&before[*index].1, value))
+            })
+            .map(|(_, property)| property)
+            .collect();
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let iface = self.connection.object_server().interface::<_, Stats>(PATH)?;
+        zbus::block_on(Properties::properties_changed(
+            iface.signal_emitter(),
+            InterfaceName::from_static_str_unchecked(INTERFACE),
+            changed,
+            Cow::Borrowed(&[]),
+        ))
+    }
+}
+
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::F64(a), Value::F64(b)) => a.to_bits() == b.to_bits(),
+        (a, b) => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::Record;
+    use crate::test_bus::TestBus;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+    use zbus::blocking::connection::Builder;
+    use zbus::blocking::fdo::PropertiesProxy;
+    use zbus::blocking::proxy::Builder as ProxyBuilder;
+    use zbus::blocking::Proxy;
+    use zbus::proxy::CacheProperties;
+
+    fn snapshot(cpu_usage: f32) -> Snapshot {
+        Snapshot {
+            cpu_usage,
+            cpu_celsius: Some(45.5),
+            memory_used_bytes: 4096,
+            memory_total_bytes: 8192,
+            interface: Some("eth0".into()),
+            download_bps: 1000,
+            ..Snapshot::default()
+        }
+    }
+
+    fn record(cpu_usage: f32, seconds: u64) -> Record {
+        Record::new(snapshot(cpu_usage), SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    fn connect(bus: &TestBus) -> Connection {
+        Builder::address(bus.address.as_str()).unwrap().build().unwrap()
+    }
+
+    #[test]
+    fn properties_and_history_are_served() {
+        let Some(bus) = TestBus::start() else {
+            return;
+        };
+        let history = Shared::default();
+        let _service = Service::start_with(connect(&bus), history.clone()).unwrap();
+        let client = connect(&bus);
+        // Read every property afresh; nothing is published to keep a cache current
+        let stats = ProxyBuilder::<Proxy>::new(&client)
+            .destination(NAME)
+            .unwrap()
+            .path(PATH)
+            .unwrap()
+            .interface(INTERFACE)
+            .unwrap()
+            .cache_properties(CacheProperties::No)
+            .build()
+            .unwrap();
+
+        // Before the first sample
+        assert!(stats.get_property::<f64>("CpuTemperature").unwrap().is_nan());
+        assert_eq!(stats.get_property::<u64>("MemoryUsed").unwrap(), 0);
+
+        let mut shared = history.lock().unwrap();
+        for (seconds, usage) in [(100, 10.0), (160, 20.0), (170, 30.0)] {
+            shared.push(record(usage, seconds));
+        }
+        drop(shared);
+
+        assert_eq!(stats.get_property::<f64>("CpuUsage").unwrap(), 30.0);
+        assert_eq!(stats.get_property::<f64>("CpuTemperature").unwrap(), 45.5);
+        assert!(stats.get_property::<f64>("GpuTemperature").unwrap().is_nan());
+        assert_eq!(stats.get_property::<u64>("MemoryTotal").unwrap(), 8192);
+        assert_eq!(stats.get_property::<u64>("DownloadRate").unwrap(), 1000);
+        assert_eq!(stats.get_property::<String>("Interface").unwrap(), "eth0");
+
+        let samples: Vec<(u64, f64)> = stats.call("GetHistory", &("cpu_usage", 10u32)).unwrap();
+        assert_eq!(samples, [(160_000, 20.0), (170_000, 30.0)]);
+        let samples: Vec<(u64, f64)> = stats.call("GetHistory", &("gpu_temperature", 60u32)).unwrap();
+        assert!(samples.is_empty());
+        let unknown = stats.call::<_, _, Vec<(u64, f64)>>("GetHistory", &("disk", 60u32));
+        assert!(matches!(unknown, Err(zbus::Error::MethodError(name, _, _))
+            if name.as_str() == "org.freedesktop.DBus.Error.InvalidArgs"));
+
+        let values: HashMap<String, zbus::zvariant::OwnedValue> =
+            stats.call("GetSnapshot", &()).unwrap();
+        assert_eq!(f64::try_from(&values["cpu_usage"]).unwrap(), 30.0);
+        assert_eq!(u64::try_from(&values["timestamp_ms"]).unwrap(), 170_000);
+        assert!(!values.contains_key("gpu_temperature"));
+    }
+
+    #[test]
+    fn publish_signals_only_changed_properties() {
+        let Some(bus) = TestBus::start() else {
+            return;
+        };
+        let history = Shared::default();
+        let mut service = Service::start_with(connect(&bus), history.clone()).unwrap();
+        let client = connect(&bus);
+        let properties = PropertiesProxy::builder(&client)
+            .destination(NAME)
+            .unwrap()
+            .path(PATH)
+            .unwrap()
+            .build()
+            .unwrap();
+        let changes = properties.receive_properties_changed().unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for signal in changes {
+                let args = signal.args().unwrap();
+                let mut names: Vec<String> =
+                    args.changed_properties().keys().map(|name| name.to_string()).collect();
+                names.sort();
+                if sender.send(names).is_err() {
+                    break;
+                }
+            }
+        });
+        let next = || receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        service.publish(&snapshot(10.0)).unwrap();
+        assert_eq!(next().len(), 8);
+
+        // Missing sensors read as NaN, which mustn't count as a change every time
+        service.publish(&snapshot(10.0)).unwrap();
+        service.publish(&Snapshot { download_bps: 5, ..snapshot(20.0) }).unwrap();
+        assert_eq!(next(), ["CpuUsage", "DownloadRate"]);
+    }
+}
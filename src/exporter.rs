@@ -0,0 +1,386 @@
+//! Prometheus endpoint
+//!
+//! An opt-in HTTP listener answering `GET /metrics` in the OpenMetrics text
+//! format. It serves whatever the collector published last, so a scrape never
+//! samples anything itself.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::{self, MonitorToggles};
+use crate::listener::AcceptLoop;
+use crate::monitors::Snapshot;
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// A scraper that stops talking is dropped after this long
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest request head read before giving up on a client
+const MAX_REQUEST_BYTES: u64 = 8 * 1024;
+
+/// The readings the endpoint serves, shared with the collector
+#[derive(Debug, Clone, Default)]
+pub struct Latest(Arc<Mutex<Option<(Snapshot, MonitorToggles)>>>);
+
+impl Latest {
+    pub fn publish(&self, snapshot: &Snapshot, monitors: &MonitorToggles) {
+        if let Ok(mut latest) = self.0.lock() {
+            *latest = Some((snapshot.clone(), monitors.clone()));
+        }
+    }
+
+    fn render(&self) -> String {
+        match self.0.lock().ok().and_then(|latest| latest.clone()) {
+            Some((snapshot, monitors)) => render(&snapshot, &monitors),
+            // Nothing sampled yet
+            None => "# EOF\n".to_string(),
+        }
+    }
+}
+
+/// A running listener, stopped when dropped
+pub struct Exporter {
+    address: SocketAddr,
+    _accepting: AcceptLoop,
+}
+
+impl Exporter {
+    pub fn start(address: SocketAddr, latest: Latest) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let accepting = AcceptLoop::spawn("exporter", listener, move |stream| serve(stream, &latest))?;
+
+        log::info!("Serving metrics on http://{}/metrics", address);
+        Ok(Self {
+            address,
+            _accepting: accepting,
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+/// Start, stop or move the listener to match the config
+pub fn sync(exporter: &mut Option<Exporter>, config: &config::Exporter, latest: &Latest) {
+    let wanted = config.enabled.then(|| SocketAddr::new(config.bind, config.port));
+    if exporter.as_ref().map(Exporter::address) == wanted {
+        return;
+    }
+
+    // Release the old port before binding, in case only the address changed
+    *exporter = None;
+    if let Some(address) = wanted {
+        match Exporter::start(address, latest.clone()) {
+            Ok(started) => *exporter = Some(started),
+            Err(e) => log::error!("Failed to serve metrics on {}: {}", address, e),
+        }
+    }
+}
+
+/// Answer one HTTP request and close the connection
+fn serve(stream: TcpStream, latest: &Latest) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_BYTES));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Headers don't change the answer, but the client expects them read
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+
+    let (status, body) = match (method, path) {
+        ("GET" | "HEAD", "/metrics") => ("200 OK", latest.render()),
+        ("GET" | "HEAD", _) => ("404 Not Found", "Metrics are served at /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed", "Only GET and HEAD are supported\n".to_string()),
+    };
+    let content_type = if status.starts_with("200") {
+        CONTENT_TYPE
+    } else {
+        "text/plain; charset=utf-8"
+    };
+
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    if status.starts_with("405") {
+        response.push_str("Allow: GET, HEAD\r\n");
+    }
+    response.push_str("\r\n");
+    if method != "HEAD" {
+        response.push_str(&body);
+    }
+
+    let mut stream = reader.into_inner().into_inner();
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+/// The snapshot as an OpenMetrics exposition, covering only enabled monitors
+pub fn render(snapshot: &Snapshot, monitors: &MonitorToggles) -> String {
+    let mut out = Metrics::default();
+
+    if monitors.cpu_usage {
+        out.family("systemstats_cpu_usage_ratio", "gauge", "ratio", "CPU usage across all cores");
+        out.sample("systemstats_cpu_usage_ratio", &[], snapshot.cpu_usage as f64 / 100.0);
+    }
+
+    if monitors.cpu_temperature {
+        if let Some(celsius) = snapshot.cpu_celsius {
+            let sensor = snapshot.cpu_sensor.as_deref().unwrap_or("unknown");
+            out.family("systemstats_cpu_temperature_celsius", "gauge", "celsius", "CPU package temperature");
+            out.sample("systemstats_cpu_temperature_celsius", &[("sensor", sensor)], celsius as f64);
+        }
+    }
+
+    if monitors.gpu_temperature && !snapshot.gpus.is_empty() {
+        out.family("systemstats_gpu_temperature_celsius", "gauge", "celsius", "GPU edge temperature");
+        for gpu in &snapshot.gpus {
+            out.sample(
+                "systemstats_gpu_temperature_celsius",
+                &[("gpu", &gpu.gpu), ("device", &gpu.device)],
+                gpu.celsius as f64,
+            );
+        }
+    }
+
+    if monitors.memory {
+        out.family("systemstats_memory_used_bytes", "gauge", "bytes", "Memory in use");
+        out.sample("systemstats_memory_used_bytes", &[], snapshot.memory_used_bytes as f64);
+        out.family("systemstats_memory_total_bytes", "gauge", "bytes", "Installed memory");
+        out.sample("systemstats_memory_total_bytes", &[], snapshot.memory_total_bytes as f64);
+    }
+
+    if monitors.network {
+        if let Some(interface) = snapshot.interface.as_deref() {
+            let labels = [("interface", interface)];
+            let rates = [
+                ("systemstats_network_receive_bytes_per_second", "Download rate", snapshot.download_bps),
+                ("systemstats_network_transmit_bytes_per_second", "Upload rate", snapshot.upload_bps),
+            ];
+            for (name, help, value) in rates {
+                out.family(name, "gauge", "bytes_per_second", help);
+                out.sample(name, &labels, value as f64);
+            }
+
+            let totals = [
+                ("systemstats_network_receive_bytes", "Bytes received since boot", snapshot.received_bytes),
+                ("systemstats_network_transmit_bytes", "Bytes sent since boot", snapshot.transmitted_bytes),
+            ];
+            for (name, help, value) in totals {
+                out.family(name, "counter", "bytes", help);
+                out.sample(&format!("{}_total", name), &labels, value as f64);
+            }
+        }
+    }
+
+    // Only sampled while an alert rule needs it
+    if let Some(percent) = snapshot.disk_free_percent {
+        let mount = snapshot.disk_mount.as_deref().unwrap_or("unknown");
+        out.family("systemstats_disk_free_ratio", "gauge", "ratio", "Free space on the fullest disk");
+        out.sample("systemstats_disk_free_ratio", &[("mountpoint", mount)], percent as f64 / 100.0);
+    }
+
+    out.0.push_str("# EOF\n");
+    out.0
+}
+
+#[derive(Default)]
+struct Metrics(String);
+
+impl Metrics {
+    fn family(&mut self, name: &str, kind: &str, unit: &str, help: &str) {
+        let _ = writeln!(self.0, "# TYPE {} {}", name, kind);
+        let _ = writeln!(self.0, "# UNIT {} {}", name, unit);
+        let _ = writeln!(self.0, "# HELP {} {}", name, help);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.0.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+                .collect();
+            let _ = write!(self.0, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.0, " {}", value);
+    }
+}
+
+/// Label values are quoted; backslashes, quotes and newlines need escaping
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitors::GpuReading;
+    use std::net::Ipv4Addr;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            cpu_usage: 25.0,
+            cpu_celsius: Some(45.5),
+            cpu_sensor: Some("k10temp Tctl".into()),
+            gpus: vec![GpuReading {
+                gpu: "card1".into(),
+                device: "amdgpu".into(),
+                celsius: 52.0,
+                path: None,
+            }],
+            memory_used_bytes: 4096,
+            memory_total_bytes: 8192,
+            interface: Some("eth0".into()),
+            download_bps: 1000,
+            upload_bps: 10,
+            received_bytes: 123,
+            transmitted_bytes: 45,
+            disk_free_percent: Some(12.5),
+            disk_mount: Some("/home".into()),
+            ..Snapshot::default()
+        }
+    }
+
+    fn all_monitors() -> MonitorToggles {
+        config::Config::default().monitors
+    }
+
+    #[test]
+    fn renders_every_enabled_monitor() {
+        let metrics = render(&snapshot(), &all_monitors());
+
+        for line in [
+            "# TYPE systemstats_cpu_usage_ratio gauge",
+            "systemstats_cpu_usage_ratio 0.25",
+            "systemstats_cpu_temperature_celsius{sensor=\"k10temp Tctl\"} 45.5",
+            "systemstats_gpu_temperature_celsius{gpu=\"card1\",device=\"amdgpu\"} 52",
+            "systemstats_memory_used_bytes 4096",
+            "systemstats_memory_total_bytes 8192",
+            "systemstats_network_receive_bytes_per_second{interface=\"eth0\"} 1000",
+            "# TYPE systemstats_network_transmit_bytes counter",
+            "systemstats_network_transmit_bytes_total{interface=\"eth0\"} 45",
+            "# UNIT systemstats_disk_free_ratio ratio",
+            "systemstats_disk_free_ratio{mountpoint=\"/home\"} 0.125",
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "missing {:?} in\n{}", line, metrics);
+        }
+        assert!(metrics.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn disabled_and_missing_readings_are_left_out() {
+        let monitors = MonitorToggles {
+            cpu_usage: false,
+            memory: false,
+            ..all_monitors()
+        };
+        let snapshot = Snapshot {
+            cpu_celsius: None,
+            interface: None,
+            disk_free_percent: None,
+            ..snapshot()
+        };
+
+        let metrics = render(&snapshot, &monitors);
+
+        assert!(!metrics.contains("cpu_usage"));
+        assert!(!metrics.contains("cpu_temperature"));
+        assert!(!metrics.contains("memory"));
+        assert!(!metrics.contains("network"));
+        assert!(!metrics.contains("disk"));
+        assert!(metrics.contains("systemstats_gpu_temperature_celsius{"));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        let snapshot = Snapshot {
+            cpu_sensor: Some("a \"b\"\\c\nd".into()),
+            ..snapshot()
+        };
+        let metrics = render(&snapshot, &all_monitors());
+
+        assert!(metrics.contains(r#"{sensor="a \"b\"\\c\nd"}"#));
+    }
+
+    #[test]
+    fn nothing_published_is_an_empty_exposition() {
+        assert_eq!(Latest::default().render(), "# EOF\n");
+    }
+
+    fn get(address: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_metrics_over_http() {
+        let latest = Latest::default();
+        latest.publish(&snapshot(), &all_monitors());
+        let exporter = Exporter::start((Ipv4Addr::LOCALHOST, 0).into(), latest).unwrap();
+        let address = exporter.address();
+
+        // A client that never sends anything doesn't hold up the others
+        let _silent = TcpStream::connect(address).unwrap();
+
+        let response = get(address, "GET /metrics?x=1 HTTP/1.1\r\nHost: test\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.ends_with("# EOF\n"));
+
+        let response = get(address, "HEAD /metrics HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n"));
+
+        assert!(get(address, "GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+        let response = get(address, "POST /metrics HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405"));
+        assert!(response.contains("Allow: GET, HEAD\r\n"));
+    }
+
+    #[test]
+    fn sync_follows_the_config() {
+        let latest = Latest::default();
+        let mut exporter = None;
+        let mut config = config::Exporter {
+            enabled: true,
+            bind: Ipv4Addr::LOCALHOST.into(),
+            port: 0,
+        };
+
+        sync(&mut exporter, &config, &latest);
+        assert!(exporter.is_some());
+
+        config.enabled = false;
+        sync(&mut exporter, &config, &latest);
+        assert!(exporter.is_none());
+    }
+}
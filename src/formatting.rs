@@ -1,25 +1,239 @@
 //! Formatting utilities for system stats display
 
-pub fn format_percentage(value: f32) -> String {
-    format!("{:.0}%", value.clamp(0.0, 100.0))
+use crate::config::{MemoryUnit, NetworkUnit, TemperatureUnit, Units};
+
+/// Same advance as a digit in most fonts, unlike a regular space
+const FIGURE_SPACE: char = '\u{2007}';
+
+const NETWORK_BIT_LABELS: [&str; 4] = ["bps", "Kbps", "Mbps", "Gbps"];
+const NETWORK_BYTE_LABELS: [&str; 4] = ["B/s", "KB/s", "MB/s", "GB/s"];
+
+pub fn format_percentage(value: f32, units: &Units) -> String {
+    let value = format!("{:.0}", value.clamp(0.0, 100.0));
+    format!("{}%", pad(value, 3, units))
 }
 
-pub fn format_celsius(value: f32) -> String {
-    format!("{:.0}°C", value)
+pub fn format_temperature(celsius: f32, units: &Units) -> String {
+    let (value, label) = convert_temperature(celsius, units.temperature);
+    format!("{}{}", pad(format!("{:.0}", value), 3, units), label)
 }
 
-pub fn format_memory_gb(value: f32) -> String {
-    format!("{:.1} GB", value)
+pub fn format_memory(bytes: u64, units: &Units) -> String {
+    let (divisor, label) = memory_scale(units.memory, 3);
+    let value = format!("{:.1}", bytes as f64 / divisor);
+    format!("{} {}", pad(value, 5, units), label)
 }
 
-/// Auto-switches to Gbps when >= 1000 Mbps
-pub fn format_network_speed(bytes_per_sec: u64) -> String {
-    let mbps = bytes_per_sec as f64 / 125_000.0;
+/// Auto-scales through K/M/G in steps of 1000
+pub fn format_network_speed(bytes_per_sec: u64, units: &Units) -> String {
+    let (value, magnitude) = network_auto_scale(bytes_per_sec, units.network);
+    let label = network_label(units.network, magnitude);
+    let value = format!("{:.*}", network_precision(magnitude), value);
 
-    if mbps >= 1000.0 {
-        let gbps = mbps / 1000.0;
-        format!("{:.2} Gbps", gbps)
+    if units.fixed_width {
+        format!("{} {:<4}", pad(value, 5, units), label)
     } else {
-        format!("{:.1} Mbps", mbps)
+        format!("{} {}", value, label)
+    }
+}
+
+/// Temperature without the scale letter, for narrow layouts
+pub fn format_temperature_short(celsius: f32, units: &Units) -> String {
+    let (value, _) = convert_temperature(celsius, units.temperature);
+    format!("{:.0}°", value)
+}
+
+/// Memory with a single-letter prefix, for narrow layouts
+pub fn format_memory_short(bytes: u64, units: &Units) -> String {
+    let (divisor, _) = memory_scale(units.memory, 3);
+    format!("{:.1}G", bytes as f64 / divisor)
+}
+
+/// Speed with a single-letter prefix and a decimal only below 10, for narrow layouts
+pub fn format_network_speed_short(bytes_per_sec: u64, units: &Units) -> String {
+    let (value, magnitude) = network_auto_scale(bytes_per_sec, units.network);
+    let prefix = ["", "K", "M", "G"][magnitude];
+    let suffix = match units.network {
+        NetworkUnit::Bits => "",
+        NetworkUnit::Bytes => "B",
+    };
+
+    if value < 10.0 && magnitude > 0 {
+        format!("{:.1}{}{}", value, prefix, suffix)
+    } else {
+        format!("{:.0}{}{}", value, prefix, suffix)
+    }
+}
+
+/// Converts to the requested scale, returning the value and its suffix
+pub fn convert_temperature(celsius: f32, unit: TemperatureUnit) -> (f32, &'static str) {
+    match unit {
+        TemperatureUnit::Celsius => (celsius, "°C"),
+        TemperatureUnit::Fahrenheit => (celsius * 9.0 / 5.0 + 32.0, "°F"),
+        TemperatureUnit::Kelvin => (celsius + 273.15, " K"),
+    }
+}
+
+/// Divisor and label for a memory prefix, where magnitude 1 is kilo and 3 is giga
+pub fn memory_scale(unit: MemoryUnit, magnitude: u32) -> (f64, &'static str) {
+    const SI: [&str; 4] = ["B", "KB", "MB", "GB"];
+    const IEC: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    match unit {
+        MemoryUnit::Si => (1000f64.powi(magnitude as i32), SI[magnitude as usize]),
+        MemoryUnit::Iec => (1024f64.powi(magnitude as i32), IEC[magnitude as usize]),
+    }
+}
+
+/// Picks the largest prefix that keeps the value at or above 1
+pub fn network_auto_scale(bytes_per_sec: u64, unit: NetworkUnit) -> (f64, usize) {
+    let mut value = network_base_value(bytes_per_sec, unit);
+    let mut magnitude = 0;
+
+    while value >= 1000.0 && magnitude < NETWORK_BIT_LABELS.len() - 1 {
+        value /= 1000.0;
+        magnitude += 1;
+    }
+
+    (value, magnitude)
+}
+
+/// Value at a fixed prefix, where magnitude 1 is kilo and 3 is giga
+pub fn network_fixed_scale(bytes_per_sec: u64, unit: NetworkUnit, magnitude: usize) -> f64 {
+    network_base_value(bytes_per_sec, unit) / 1000f64.powi(magnitude as i32)
+}
+
+pub fn network_label(unit: NetworkUnit, magnitude: usize) -> &'static str {
+    match unit {
+        NetworkUnit::Bits => NETWORK_BIT_LABELS[magnitude],
+        NetworkUnit::Bytes => NETWORK_BYTE_LABELS[magnitude],
+    }
+}
+
+pub fn network_precision(magnitude: usize) -> usize {
+    match magnitude {
+        0 => 0,
+        3 => 2,
+        _ => 1,
+    }
+}
+
+fn network_base_value(bytes_per_sec: u64, unit: NetworkUnit) -> f64 {
+    match unit {
+        NetworkUnit::Bits => bytes_per_sec as f64 * 8.0,
+        NetworkUnit::Bytes => bytes_per_sec as f64,
+    }
+}
+
+/// Left-pads with figure spaces when fixed-width output is enabled
+fn pad(value: String, width: usize, units: &Units) -> String {
+    let len = value.chars().count();
+    if !units.fixed_width || len >= width {
+        return value;
+    }
+
+    let mut padded: String = std::iter::repeat_n(FIGURE_SPACE, width - len).collect();
+    padded.push_str(&value);
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn units(temperature: TemperatureUnit, memory: MemoryUnit, network: NetworkUnit) -> Units {
+        Units {
+            temperature,
+            memory,
+            network,
+            fixed_width: false,
+        }
+    }
+
+    fn fixed() -> Units {
+        Units {
+            fixed_width: true,
+            ..Units::default()
+        }
+    }
+
+    #[test]
+    fn percentage_is_clamped_and_rounded() {
+        let units = Units::default();
+        assert_eq!(format_percentage(42.6, &units), "43%");
+        assert_eq!(format_percentage(-3.0, &units), "0%");
+        assert_eq!(format_percentage(140.0, &units), "100%");
+    }
+
+    #[test]
+    fn temperature_follows_the_unit() {
+        let celsius = Units::default();
+        let fahrenheit = units(TemperatureUnit::Fahrenheit, MemoryUnit::Iec, NetworkUnit::Bits);
+        let kelvin = units(TemperatureUnit::Kelvin, MemoryUnit::Iec, NetworkUnit::Bits);
+
+        assert_eq!(format_temperature(55.4, &celsius), "55°C");
+        assert_eq!(format_temperature(100.0, &fahrenheit), "212°F");
+        assert_eq!(format_temperature(0.0, &kelvin), "273 K");
+        assert_eq!(format_temperature_short(100.0, &fahrenheit), "212°");
+    }
+
+    #[test]
+    fn memory_uses_binary_or_decimal_prefixes() {
+        let si = units(TemperatureUnit::Celsius, MemoryUnit::Si, NetworkUnit::Bits);
+
+        assert_eq!(format_memory(4 * GIB, &Units::default()), "4.0 GiB");
+        assert_eq!(format_memory(4 * GIB, &si), "4.3 GB");
+        assert_eq!(format_memory_short(GIB / 2, &Units::default()), "0.5G");
+        assert_eq!(memory_scale(MemoryUnit::Si, 2), (1_000_000.0, "MB"));
+        assert_eq!(memory_scale(MemoryUnit::Iec, 1), (1024.0, "KiB"));
+    }
+
+    #[test]
+    fn network_speed_scales_in_thousands() {
+        let bytes = units(TemperatureUnit::Celsius, MemoryUnit::Iec, NetworkUnit::Bytes);
+
+        assert_eq!(format_network_speed(0, &Units::default()), "0 bps");
+        assert_eq!(format_network_speed(125, &Units::default()), "1.0 Kbps");
+        assert_eq!(format_network_speed(1_300_000, &Units::default()), "10.4 Mbps");
+        assert_eq!(format_network_speed(2_000_000_000, &Units::default()), "16.00 Gbps");
+        assert_eq!(format_network_speed(999, &bytes), "999 B/s");
+        assert_eq!(format_network_speed(1_500_000, &bytes), "1.5 MB/s");
+    }
+
+    #[test]
+    fn network_speed_stops_at_giga() {
+        let (value, magnitude) = network_auto_scale(u64::MAX / 8, NetworkUnit::Bits);
+        assert_eq!(magnitude, 3);
+        assert!(value > 1000.0);
+        assert_eq!(network_fixed_scale(1_000_000, NetworkUnit::Bytes, 2), 1.0);
+    }
+
+    #[test]
+    fn short_network_speed_keeps_a_decimal_below_ten() {
+        let bytes = units(TemperatureUnit::Celsius, MemoryUnit::Iec, NetworkUnit::Bytes);
+
+        assert_eq!(format_network_speed_short(1_000_000, &Units::default()), "8.0M");
+        assert_eq!(format_network_speed_short(1_500_000, &Units::default()), "12M");
+        assert_eq!(format_network_speed_short(500, &bytes), "500B");
+        assert_eq!(format_network_speed_short(2_500, &bytes), "2.5KB");
+    }
+
+    #[test]
+    fn fixed_width_pads_with_figure_spaces() {
+        assert_eq!(format_percentage(5.0, &fixed()), "\u{2007}\u{2007}5%");
+        assert_eq!(format_percentage(100.0, &fixed()), "100%");
+        assert_eq!(format_temperature(9.0, &fixed()), "\u{2007}\u{2007}9°C");
+        assert_eq!(format_memory(GIB, &fixed()), "\u{2007}\u{2007}1.0 GiB");
+        assert_eq!(format_network_speed(125, &fixed()), "\u{2007}\u{2007}1.0 Kbps");
+        assert_eq!(format_network_speed(1, &fixed()), "\u{2007}\u{2007}\u{2007}\u{2007}8 bps ");
+
+        // Every value of a unit comes out the same width
+        let widths: Vec<usize> = [0, 999, 1_000_000, 999_000_000]
+            .iter()
+            .map(|&bps| format_network_speed(bps, &fixed()).chars().count())
+            .collect();
+        assert!(widths.iter().all(|&width| width == widths[0]), "{:?}", widths);
     }
 }
@@ -0,0 +1,123 @@
+//! Recent samples for clients asking what happened lately
+//!
+//! The collector keeps the last hour here once, and the D-Bus service and the
+//! query socket both read from it.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::validate::REFRESH_INTERVAL_MIN_MS;
+use crate::recording::Record;
+
+/// How far back history reaches
+pub const WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Most samples kept: a full window at the shortest interval
+///
+/// Samples carry wall-clock time, so a clock set back would otherwise keep
+/// everything until it caught up again.
+pub const MAX_RECORDS: usize = (WINDOW.as_millis() / REFRESH_INTERVAL_MIN_MS as u128) as usize;
+
+/// History shared between the collector and the services reading it
+pub type Shared = Arc<Mutex<History>>;
+
+/// Samples from the last [`WINDOW`], at most [`MAX_RECORDS`] of them, oldest first
+#[derive(Debug, Default)]
+pub struct History {
+    records: VecDeque<Record>,
+}
+
+impl History {
+    /// Add a sample and drop the ones that fell out of the window or past the cap
+    pub fn push(&mut self, record: Record) {
+        let cutoff = record.time_ms.saturating_sub(WINDOW.as_millis() as u64);
+        while self
+            .records
+            .front()
+            .is_some_and(|oldest| oldest.time_ms < cutoff || self.records.len() >= MAX_RECORDS)
+        {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn latest(&self) -> Option<&Record> {
+        self.records.back()
+    }
+
+    /// Samples taken within `seconds` of the newest one, oldest first
+    pub fn since(&self, seconds: u64) -> impl Iterator<Item = &Record> {
+        let since = self.latest().map_or(0, |newest| {
+            newest.time_ms.saturating_sub(seconds.saturating_mul(1000))
+        });
+        self.records
+            .iter()
+            .filter(move |record| record.time_ms >= since)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitors::Snapshot;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn record(seconds: u64) -> Record {
+        Record::new(Snapshot::default(), UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    fn times(records: impl Iterator<Item = Record>) -> Vec<u64> {
+        records.map(|record| record.time_ms / 1000).collect()
+    }
+
+    #[test]
+    fn old_samples_fall_out_of_the_window() {
+        let mut history = History::default();
+        assert!(history.latest().is_none());
+
+        for seconds in [0, 1800, 3600, 3601] {
+            history.push(record(seconds));
+        }
+
+        assert_eq!(times(history.since(u64::MAX).cloned()), [1800, 3600, 3601]);
+        assert_eq!(history.latest().map(|record| record.time_ms), Some(3_601_000));
+    }
+
+    #[test]
+    fn since_counts_back_from_the_newest_sample() {
+        let mut history = History::default();
+        assert_eq!(history.since(60).count(), 0);
+
+        for seconds in [100, 150, 160, 170] {
+            history.push(record(seconds));
+        }
+
+        assert_eq!(times(history.since(20).cloned()), [150, 160, 170]);
+        assert_eq!(times(history.since(0).cloned()), [170]);
+    }
+
+    #[test]
+    fn count_is_capped_when_the_clock_goes_back() {
+        let mut history = History::default();
+        let now = SystemTime::now();
+
+        for _ in 0..MAX_RECORDS + 10 {
+            history.push(Record::new(Snapshot::default(), now));
+        }
+        assert_eq!(history.len(), MAX_RECORDS);
+
+        // Earlier than everything kept, so the window alone would never drop those
+        history.push(Record::new(Snapshot::default(), now - Duration::from_secs(60)));
+        assert_eq!(history.len(), MAX_RECORDS);
+        assert!(!history.is_empty());
+    }
+}
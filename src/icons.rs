@@ -0,0 +1,22 @@
+//! Bundled symbolic icons for the panel segments
+
+use cosmic::widget::icon;
+
+use crate::segments::SegmentKind;
+
+const CPU: &[u8] = include_bytes!("../res/icons/cpu-symbolic.svg");
+const GPU: &[u8] = include_bytes!("../res/icons/gpu-symbolic.svg");
+const MEMORY: &[u8] = include_bytes!("../res/icons/memory-symbolic.svg");
+const NETWORK: &[u8] = include_bytes!("../res/icons/network-symbolic.svg");
+
+/// Symbolic handle, recoloured by the theme like any other panel icon
+pub fn segment_icon(kind: SegmentKind) -> icon::Handle {
+    let bytes = match kind {
+        SegmentKind::Cpu => CPU,
+        SegmentKind::Gpu => GPU,
+        SegmentKind::Memory => MEMORY,
+        SegmentKind::Network => NETWORK,
+    };
+
+    icon::from_svg_bytes(bytes).symbolic(true)
+}
@@ -0,0 +1,99 @@
+//! Layout selection from the panel the applet is placed on
+
+use crate::config::LabelStyle;
+
+/// Smallest icon size at which a vertical panel has room for an icon above each value
+const MIN_ICON_SIZE: u16 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Segments side by side with full labels
+    Row,
+    /// Segments stacked top to bottom
+    Column,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub direction: Direction,
+    /// Use short unit suffixes so values fit the panel's thickness
+    pub abbreviate: bool,
+    /// Room for a segment icon; segments set to show one fall back to text without it
+    pub icon_room: bool,
+}
+
+impl Layout {
+    /// Whether a segment with this label style is introduced by its icon
+    pub fn shows_icon(self, style: LabelStyle) -> bool {
+        style == LabelStyle::Icon && self.icon_room
+    }
+}
+
+/// Pick a layout for a panel of the given orientation and suggested icon size
+pub fn choose(orientation: Orientation, icon_size: u16) -> Layout {
+    match orientation {
+        Orientation::Horizontal => Layout {
+            direction: Direction::Row,
+            abbreviate: false,
+            icon_room: true,
+        },
+        Orientation::Vertical => Layout {
+            direction: Direction::Column,
+            abbreviate: true,
+            icon_room: icon_size >= MIN_ICON_SIZE,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn horizontal_panels_use_a_full_row() {
+        for icon_size in [0, MIN_ICON_SIZE - 1, MIN_ICON_SIZE, 64] {
+            assert_eq!(
+                choose(Orientation::Horizontal, icon_size),
+                Layout {
+                    direction: Direction::Row,
+                    abbreviate: false,
+                    icon_room: true,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn vertical_panels_stack_abbreviated_values() {
+        let layout = choose(Orientation::Vertical, 32);
+        assert_eq!(layout.direction, Direction::Column);
+        assert!(layout.abbreviate);
+    }
+
+    #[test]
+    fn vertical_panels_fit_icons_from_the_minimum_size() {
+        assert!(!choose(Orientation::Vertical, MIN_ICON_SIZE - 1).icon_room);
+        assert!(choose(Orientation::Vertical, MIN_ICON_SIZE).icon_room);
+        assert!(choose(Orientation::Vertical, 48).icon_room);
+    }
+
+    #[test]
+    fn icons_follow_each_segment_style() {
+        let large = choose(Orientation::Vertical, 48);
+        assert!(large.shows_icon(LabelStyle::Icon));
+        assert!(!large.shows_icon(LabelStyle::Text));
+
+        // Too thin for an icon, so the label is text whatever the style
+        let small = choose(Orientation::Vertical, MIN_ICON_SIZE - 1);
+        assert!(!small.shows_icon(LabelStyle::Icon));
+        assert!(!small.shows_icon(LabelStyle::Text));
+
+        assert!(choose(Orientation::Horizontal, 0).shows_icon(LabelStyle::Icon));
+    }
+}
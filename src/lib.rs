@@ -0,0 +1,33 @@
+//! System stats applet for the COSMIC panel
+//!
+//! `main.rs` only parses arguments and picks a mode; everything else lives
+//! here so the integration tests in `tests/` can reach it.
+
+pub mod actions;
+pub mod adaptive;
+pub mod alerts;
+pub mod app;
+pub mod args;
+pub mod bar;
+pub mod cli;
+pub mod collector;
+pub mod config;
+pub mod dbus;
+pub mod exporter;
+pub mod formatting;
+pub mod history;
+pub mod icons;
+pub mod layout;
+pub mod listener;
+pub mod monitors;
+pub mod recording;
+pub mod sample_log;
+pub mod segments;
+pub mod settings;
+pub mod socket;
+pub mod sysfs;
+pub mod template;
+#[cfg(test)]
+mod test_bus;
+pub mod thresholds;
+
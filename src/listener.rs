@@ -0,0 +1,175 @@
+//! Accept loops for the metrics endpoint and the query socket
+//!
+//! The listener is polled without blocking, so stopping only takes a flag
+//! and a join. Waking a blocked `accept()` by connecting to ourselves fails
+//! whenever the bound address can't be reached, which left the thread
+//! running. Every connection is handled on its own thread, so a slow or
+//! silent client never holds up the next one.
+
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long a stop request may go unnoticed
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A socket that hands out connections
+pub trait Listener: Send + 'static {
+    type Stream: Send + 'static;
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+
+    /// The next pending connection, in blocking mode
+    fn accept_stream(&self) -> io::Result<Self::Stream>;
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpListener::set_nonblocking(self, nonblocking)
+    }
+
+    fn accept_stream(&self) -> io::Result<TcpStream> {
+        let (stream, _) = self.accept()?;
+        stream.set_nonblocking(false)?;
+        Ok(stream)
+    }
+}
+
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixListener::set_nonblocking(self, nonblocking)
+    }
+
+    fn accept_stream(&self) -> io::Result<UnixStream> {
+        let (stream, _) = self.accept()?;
+        stream.set_nonblocking(false)?;
+        Ok(stream)
+    }
+}
+
+/// A background thread accepting connections, stopped when dropped
+#[derive(Debug)]
+pub struct AcceptLoop {
+    stopping: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AcceptLoop {
+    /// Accept on a thread called `name`, handing each connection to `handle` on a thread of its own
+    pub fn spawn<L, F>(name: &'static str, listener: L, handle: F) -> io::Result<Self>
+    where
+        L: Listener,
+        F: Fn(L::Stream) -> io::Result<()> + Send + Sync + 'static,
+    {
+        listener.set_nonblocking(true)?;
+        let stopping = Arc::new(AtomicBool::new(false));
+        let handle = Arc::new(handle);
+
+        let thread = thread::Builder::new().name(name.into()).spawn({
+            let stopping = stopping.clone();
+            move || {
+                while !stopping.load(Ordering::Relaxed) {
+                    let stream = match listener.accept_stream() {
+                        Ok(stream) => stream,
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            thread::sleep(POLL_INTERVAL);
+                            continue;
+                        }
+                        Err(e) => {
+                            log::warn!("Failed to accept {} connection: {}", name, e);
+                            thread::sleep(POLL_INTERVAL);
+                            continue;
+                        }
+                    };
+
+                    let handle = handle.clone();
+                    let spawned = thread::Builder::new()
+                        .name(format!("{}-client", name))
+                        .spawn(move || {
+                            if let Err(e) = handle(stream) {
+                                log::debug!("{} client failed: {}", name, e);
+                            }
+                        });
+                    if let Err(e) = spawned {
+                        log::warn!("Failed to start {} client: {}", name, e);
+                    }
+                }
+            }
+        })?;
+
+        Ok(Self {
+            stopping,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for AcceptLoop {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::Ipv4Addr;
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    #[test]
+    fn connections_are_handled_concurrently() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let accepted = AcceptLoop::spawn("test", listener, |mut stream: TcpStream| {
+            let mut byte = [0];
+            stream.read_exact(&mut byte)?;
+            stream.write_all(&byte)
+        })
+        .unwrap();
+
+        // Holds its handler thread until the end
+        let _silent = TcpStream::connect(address).unwrap();
+        let mut client = TcpStream::connect(address).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client.write_all(b"x").unwrap();
+        let mut byte = [0];
+        client.read_exact(&mut byte).unwrap();
+        assert_eq!(&byte, b"x");
+
+        drop(accepted);
+    }
+
+    #[test]
+    fn drop_stops_without_a_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let accepted = AcceptLoop::spawn("test", listener, move |_| {
+            let _ = sender.send(());
+            Ok(())
+        })
+        .unwrap();
+
+        // Nothing can connect any more, which used to leave the thread blocked in accept()
+        std::fs::remove_file(&path).unwrap();
+        let start = Instant::now();
+        drop(accepted);
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -1,10 +1,8 @@
-mod app;
-mod config;
-mod formatting;
-mod monitors;
-
-use app::SystemStats;
-use config::Config;
+use cosmic_applet_systemstats::app::{Flags, SystemStats};
+use cosmic_applet_systemstats::args::{self, Args};
+use cosmic_applet_systemstats::config::Config;
+use cosmic_applet_systemstats::template::Template;
+use cosmic_applet_systemstats::{cli, collector, socket};
 use log::info;
 use std::io;
 
@@ -32,21 +30,96 @@ fn setup_logger() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Warnings only, on stderr, so they never mix with the stats on stdout
+fn setup_cli_logger() -> Result<(), log::SetLoggerError> {
+    fern::Dispatch::new()
+        .level(log::LevelFilter::Warn)
+        .chain(io::stderr())
+        .apply()
+}
+
+fn run_cli(args: &Args) -> io::Result<()> {
+    setup_cli_logger().expect("Failed to initialize logger");
+
+    // Only reads, so running alongside the applet or before it ever started leaves no trace
+    let (mut config, issues) = Config::load_read_only();
+    for issue in &issues {
+        log::warn!("Config: {}", issue);
+    }
+    if let Some(format) = &args.format {
+        match Template::parse(format) {
+            Ok(template) => config.format = Some(template),
+            Err(e) => {
+                eprintln!("error: --format: {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    cli::run(&config, args.output, args.once)
+}
+
 fn main() -> cosmic::iced::Result {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, args::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", args::USAGE);
+        return Ok(());
+    }
+
+    if let Some(request) = &args.query {
+        let Some(path) = socket::path() else {
+            eprintln!("error: XDG_RUNTIME_DIR is not set, so there is no socket to query");
+            std::process::exit(1);
+        };
+        match socket::query(&path, request) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                eprintln!("error: {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+            _ => return Ok(()),
+        }
+    }
+
+    if args.cli {
+        match run_cli(&args) {
+            // The reader went away, e.g. `| head -1`
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+            _ => return Ok(()),
+        }
+    }
+
     setup_logger().expect("Failed to initialize logger");
 
     info!("Starting systemstats applet v{}", env!("CARGO_PKG_VERSION"));
 
-    let config = Config::load();
+    let (config_handler, config, issues) = Config::load();
     info!(
-        "Config: refresh={}ms, cpu={}, cpu_temp={}, gpu_temp={}, mem={}, net={}",
+        "Config: refresh={}ms, cpu={}, cpu_temp={}, gpu_temp={}, mem={}, net={}, alerts={}",
         config.refresh_interval_ms,
         config.monitors.cpu_usage,
         config.monitors.cpu_temperature,
         config.monitors.gpu_temperature,
         config.monitors.memory,
-        config.monitors.network
+        config.monitors.network,
+        config.alerts.len()
     );
 
-    cosmic::applet::run::<SystemStats>(config)
+    cosmic::applet::run::<SystemStats>(Flags {
+        config_handler,
+        config,
+        issues,
+        collector: collector::Options {
+            replay: args.replay,
+            record: args.record,
+        },
+    })
 }
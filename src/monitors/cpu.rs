@@ -1,22 +1,125 @@
-use sysinfo::System;
+use std::fs;
+use std::path::{Path, PathBuf};
 
+use crate::sysfs::Root;
+
+const STAT: &str = "/proc/stat";
+
+/// CPU usage from the aggregate line of `/proc/stat`
 pub struct CpuStats {
-    system: System,
+    path: PathBuf,
+    previous: Option<CpuTimes>,
+    usage: f32,
+}
+
+/// Jiffies since boot, summed over every CPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuTimes {
+    pub busy: u64,
+    pub total: u64,
 }
 
 impl CpuStats {
-    pub fn new() -> Self {
-        let mut system = System::new();
-        system.refresh_cpu_usage();
+    pub fn new(root: &Root) -> Self {
+        let path = root.join(STAT);
+        let previous = read_times(&path);
+        if previous.is_none() {
+            log::warn!("Failed to read CPU times from {}", path.display());
+        }
 
-        Self { system }
+        // Usage is a difference, so the first value comes with the next update
+        Self {
+            path,
+            previous,
+            usage: 0.0,
+        }
     }
 
     pub fn update(&mut self) {
-        self.system.refresh_cpu_usage();
+        let Some(times) = read_times(&self.path) else {
+            self.usage = 0.0;
+            return;
+        };
+
+        if let Some(previous) = self.previous {
+            self.usage = usage_between(previous, times);
+        }
+        self.previous = Some(times);
     }
 
     pub fn usage(&self) -> f32 {
-        self.system.global_cpu_usage()
+        self.usage
+    }
+}
+
+fn read_times(path: &Path) -> Option<CpuTimes> {
+    parse_stat(&fs::read_to_string(path).ok()?)
+}
+
+/// Parse the `cpu` line: user nice system idle iowait irq softirq steal guest guest_nice
+///
+/// Guest time is already counted in user and nice, so it's left out of the total.
+pub fn parse_stat(contents: &str) -> Option<CpuTimes> {
+    let line = contents.lines().find(|line| line.starts_with("cpu "))?;
+    let fields: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .take(8)
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .ok()?;
+    if fields.len() < 4 {
+        return None;
+    }
+
+    let total: u64 = fields.iter().sum();
+    let idle = fields[3] + fields.get(4).copied().unwrap_or(0);
+    Some(CpuTimes {
+        busy: total - idle,
+        total,
+    })
+}
+
+/// Percentage of time spent busy between two readings
+pub fn usage_between(previous: CpuTimes, next: CpuTimes) -> f32 {
+    let total = next.total.saturating_sub(previous.total);
+    if total == 0 {
+        return 0.0;
+    }
+    let busy = next.busy.saturating_sub(previous.busy);
+    (busy as f64 / total as f64 * 100.0).min(100.0) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STAT: &str = "\
+cpu  4705 150 1120 16250 520 30 25 0 100 0
+cpu0 2355 75 560 8125 260 15 12 0 50 0
+cpu1 2350 75 560 8125 260 15 13 0 50 0
+intr 114930548 113199788 3 0 5 263 0 4 [... lots more numbers ...]
+ctxt 1990473
+";
+
+    #[test]
+    fn parses_aggregate_line() {
+        assert_eq!(parse_stat(STAT), Some(CpuTimes { busy: 6030, total: 22800 }));
+    }
+
+    #[test]
+    fn rejects_missing_or_malformed_line() {
+        assert_eq!(parse_stat("cpu0 1 2 3 4\n"), None);
+        assert_eq!(parse_stat("cpu  1 2 x 4\n"), None);
+        assert_eq!(parse_stat("cpu  1 2\n"), None);
+    }
+
+    #[test]
+    fn usage_is_busy_share_of_elapsed_time() {
+        let previous = CpuTimes { busy: 1000, total: 4000 };
+        assert_eq!(usage_between(previous, CpuTimes { busy: 1250, total: 5000 }), 25.0);
+        assert_eq!(usage_between(previous, previous), 0.0);
+        // Counters reset, e.g. a replaced fixture
+        assert_eq!(usage_between(previous, CpuTimes { busy: 10, total: 20 }), 0.0);
     }
 }
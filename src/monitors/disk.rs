@@ -0,0 +1,31 @@
+use sysinfo::{DiskRefreshKind, Disks};
+
+/// Free space on mounted disks, sampled only for alert rules that ask for it
+#[derive(Default)]
+pub struct DiskStats {
+    disks: Disks,
+}
+
+impl DiskStats {
+    pub fn new() -> Self {
+        Self { disks: Disks::new() }
+    }
+
+    /// Re-read the mount list and each disk's free space
+    pub fn update(&mut self) {
+        self.disks
+            .refresh_specifics(true, DiskRefreshKind::nothing().with_storage());
+    }
+
+    /// Mount point of the fullest disk and its free space, in percent
+    pub fn fullest(&self) -> Option<(String, f32)> {
+        self.disks
+            .iter()
+            .filter(|disk| disk.total_space() > 0)
+            .map(|disk| {
+                let free = disk.available_space() as f32 / disk.total_space() as f32 * 100.0;
+                (disk.mount_point().display().to_string(), free)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
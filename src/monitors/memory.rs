@@ -1,26 +1,95 @@
-use sysinfo::System;
+use std::fs;
+use std::path::PathBuf;
 
+use crate::sysfs::Root;
+
+const MEMINFO: &str = "/proc/meminfo";
+
+/// RAM usage from `/proc/meminfo`; swap isn't shown
 pub struct MemoryStats {
-    system: System,
+    path: PathBuf,
+    used_bytes: u64,
+    total_bytes: u64,
 }
 
 impl MemoryStats {
-    pub fn new() -> Self {
-        let mut system = System::new();
-        system.refresh_memory();
-
-        Self { system }
+    pub fn new(root: &Root) -> Self {
+        let mut stats = Self {
+            path: root.join(MEMINFO),
+            used_bytes: 0,
+            total_bytes: 0,
+        };
+        stats.update();
+        if stats.total_bytes == 0 {
+            log::warn!("Failed to read memory usage from {}", stats.path.display());
+        }
+        stats
     }
 
     pub fn update(&mut self) {
-        self.system.refresh_memory();
+        let (used, total) = fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|contents| parse_meminfo(&contents))
+            .unwrap_or_default();
+        self.used_bytes = used;
+        self.total_bytes = total;
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.used_bytes
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+}
+
+/// Used and total bytes, counting reclaimable cache as free
+///
+/// Kernels before 3.14 lack `MemAvailable`, so free plus cache stands in.
+pub fn parse_meminfo(contents: &str) -> Option<(u64, u64)> {
+    let field = |name: &str| {
+        contents.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix(':')?;
+            let kib: u64 = value.trim().trim_end_matches("kB").trim().parse().ok()?;
+            Some(kib * 1024)
+        })
+    };
+
+    let total = field("MemTotal")?;
+    let available = field("MemAvailable").or_else(|| {
+        Some(field("MemFree")? + field("Buffers").unwrap_or(0) + field("Cached").unwrap_or(0))
+    })?;
+    Some((total.saturating_sub(available), total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn used_is_total_minus_available() {
+        let meminfo = "\
+MemTotal:       16318412 kB
+MemFree:         2149932 kB
+MemAvailable:   10485760 kB
+Buffers:          512000 kB
+Cached:          7340032 kB
+";
+        assert_eq!(
+            parse_meminfo(meminfo),
+            Some(((16318412 - 10485760) * 1024, 16318412 * 1024))
+        );
     }
 
-    pub fn used_gb(&self) -> f32 {
-        self.system.used_memory() as f32 / 1_073_741_824.0
+    #[test]
+    fn old_kernels_fall_back_to_free_and_cache() {
+        let meminfo = "MemTotal: 1000 kB\nMemFree: 200 kB\nBuffers: 50 kB\nCached: 250 kB\n";
+        assert_eq!(parse_meminfo(meminfo), Some((500 * 1024, 1000 * 1024)));
     }
 
-    pub fn total_gb(&self) -> f32 {
-        self.system.total_memory() as f32 / 1_073_741_824.0
+    #[test]
+    fn missing_total_fails() {
+        assert_eq!(parse_meminfo("MemFree: 200 kB\n"), None);
     }
 }
@@ -1,42 +1,131 @@
 pub mod cpu;
+pub mod disk;
 pub mod memory;
 pub mod network;
+pub mod schedule;
+pub mod snapshot;
 pub mod temperature;
 
+use std::time::{Duration, Instant};
+
 use crate::config::Config;
+use crate::sysfs::Root;
+use schedule::{Schedule, Source};
+pub use snapshot::{GpuReading, Snapshot};
 
+/// The readers enabled monitors need, each reading through the same [`Root`]
+///
+/// A disabled monitor has no reader, so it costs nothing per tick.
 pub struct MonitorStats {
-    pub cpu: cpu::CpuStats,
-    pub memory: memory::MemoryStats,
-    pub network: network::NetworkStats,
-    pub temperature: temperature::TemperatureStats,
+    pub cpu: Option<cpu::CpuStats>,
+    pub memory: Option<memory::MemoryStats>,
+    pub network: Option<network::NetworkStats>,
+    pub temperature: Option<temperature::TemperatureStats>,
+    pub disk: Option<disk::DiskStats>,
+    schedule: Schedule,
+    root: Root,
 }
 
 impl MonitorStats {
-    pub fn new(_config: &Config) -> Self {
-        Self {
-            cpu: cpu::CpuStats::new(),
-            memory: memory::MemoryStats::new(),
-            network: network::NetworkStats::new(),
-            temperature: temperature::TemperatureStats::new(),
+    pub fn new(config: &Config, root: &Root) -> Self {
+        let mut stats = Self {
+            cpu: None,
+            memory: None,
+            network: None,
+            temperature: None,
+            disk: None,
+            schedule: Schedule::default(),
+            root: root.clone(),
+        };
+        for source in Source::ALL.into_iter().filter(|source| source.enabled(config)) {
+            stats.start(source);
+        }
+        stats
+    }
+
+    /// Build readers for monitors that were just switched on and drop those switched off,
+    /// so a monitor's first values aren't computed against a stale baseline
+    pub fn reconfigure(&mut self, old: &Config, new: &Config) {
+        for source in Source::ALL {
+            match (source.enabled(old), source.enabled(new)) {
+                (false, true) => self.start(source),
+                (true, false) => self.stop(source),
+                _ => {}
+            }
         }
     }
 
-    pub fn update(&mut self, config: &Config) {
-        if config.monitors.cpu_usage {
-            self.cpu.update();
+    fn start(&mut self, source: Source) {
+        let root = &self.root;
+        match source {
+            Source::Cpu => self.cpu = Some(cpu::CpuStats::new(root)),
+            Source::Memory => self.memory = Some(memory::MemoryStats::new(root)),
+            Source::Network => self.network = Some(network::NetworkStats::new(root)),
+            Source::Temperature => self.temperature = Some(temperature::TemperatureStats::new(root)),
+            Source::Disk => self.disk = Some(disk::DiskStats::new()),
         }
+    }
 
-        if config.monitors.memory {
-            self.memory.update();
+    fn stop(&mut self, source: Source) {
+        match source {
+            Source::Cpu => self.cpu = None,
+            Source::Memory => self.memory = None,
+            Source::Network => self.network = None,
+            Source::Temperature => self.temperature = None,
+            Source::Disk => self.disk = None,
         }
+    }
 
-        if config.monitors.network {
-            self.network.update();
+    /// Sample every enabled monitor whose interval has elapsed, returning whether any were
+    pub fn update(&mut self, config: &Config, now: Instant) -> bool {
+        let due = Source::ALL.map(|source| source.enabled(config) && self.schedule.take_due(source, config, now));
+
+        for source in Source::ALL.into_iter().filter(|&source| due[source as usize]) {
+            match source {
+                Source::Cpu => self.cpu.iter_mut().for_each(cpu::CpuStats::update),
+                Source::Memory => self.memory.iter_mut().for_each(memory::MemoryStats::update),
+                Source::Network => self.network.iter_mut().for_each(network::NetworkStats::update),
+                Source::Temperature => self.temperature.iter_mut().for_each(temperature::TemperatureStats::update),
+                Source::Disk => self.disk.iter_mut().for_each(disk::DiskStats::update),
+            }
         }
 
-        if config.monitors.cpu_temperature || config.monitors.gpu_temperature {
-            self.temperature.update();
+        due.contains(&true)
+    }
+}
+
+impl MonitorStats {
+    /// Stretch every interval by `pace`, 1 being the configured rate
+    pub fn set_pace(&mut self, pace: u32) {
+        self.schedule.set_pace(pace);
+    }
+
+    /// Time until the next tick at the current pace
+    pub fn tick(&self, config: &Config) -> Duration {
+        self.schedule.tick(config)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let (cpu, memory, network) = (self.cpu.as_ref(), self.memory.as_ref(), self.network.as_ref());
+        let temperature = self.temperature.as_ref();
+        let (disk_mount, disk_free_percent) =
+            self.disk.as_ref().and_then(|disk| disk.fullest()).unzip();
+        Snapshot {
+            cpu_usage: cpu.map_or(0.0, |cpu| cpu.usage()),
+            cpu_celsius: temperature.and_then(|temperature| temperature.cpu_celsius()),
+            cpu_sensor: temperature.and_then(|temperature| temperature.cpu_sensor()),
+            cpu_sensor_path: temperature.and_then(|temperature| temperature.cpu_sensor_path()),
+            gpu_celsius: temperature.and_then(|temperature| temperature.gpu_celsius()),
+            gpus: temperature.map_or_else(Vec::new, |temperature| temperature.gpus().to_vec()),
+            memory_used_bytes: memory.map_or(0, |memory| memory.used_bytes()),
+            memory_total_bytes: memory.map_or(0, |memory| memory.total_bytes()),
+            interface: network.and_then(|network| network.interface()).map(str::to_string),
+            download_bps: network.map_or(0, |network| network.download_bps()),
+            upload_bps: network.map_or(0, |network| network.upload_bps()),
+            received_bytes: network.map_or(0, |network| network.received_bytes()),
+            transmitted_bytes: network.map_or(0, |network| network.transmitted_bytes()),
+            disk_free_percent,
+            disk_mount,
         }
     }
 }
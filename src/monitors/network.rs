@@ -1,42 +1,51 @@
-use sysinfo::Networks;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
+use crate::sysfs::Root;
+
+const NET_DEV: &str = "/proc/net/dev";
+
+/// Transfer rates of the primary interface, from `/proc/net/dev`
 pub struct NetworkStats {
-    networks: Networks,
+    path: PathBuf,
     primary_interface: Option<String>,
     prev_rx_bytes: u64,
     prev_tx_bytes: u64,
+    prev_sample: Instant,
     rx_bytes_per_sec: u64,
     tx_bytes_per_sec: u64,
 }
 
 impl NetworkStats {
-    pub fn new() -> Self {
-        let networks = Networks::new_with_refreshed_list();
+    pub fn new(root: &Root) -> Self {
+        let path = root.join(NET_DEV);
+        let networks = read_counters(&path);
         let mut primary_interface = None;
         let mut prev_rx_bytes = 0;
         let mut prev_tx_bytes = 0;
 
         // Detect primary network interface
         // First, try to find a non-loopback interface with traffic
-        for (interface_name, data) in &networks {
-            if interface_name == "lo" {
+        for data in &networks {
+            if data.name == "lo" {
                 continue;
             }
-            if data.total_received() > 0 || data.total_transmitted() > 0 {
-                primary_interface = Some(interface_name.to_string());
-                prev_rx_bytes = data.total_received();
-                prev_tx_bytes = data.total_transmitted();
+            if data.received > 0 || data.transmitted > 0 {
+                primary_interface = Some(data.name.clone());
+                prev_rx_bytes = data.received;
+                prev_tx_bytes = data.transmitted;
                 break;
             }
         }
 
         // If no interface with traffic found, use first non-loopback interface
         if primary_interface.is_none() {
-            for (interface_name, data) in &networks {
-                if interface_name != "lo" {
-                    primary_interface = Some(interface_name.to_string());
-                    prev_rx_bytes = data.total_received();
-                    prev_tx_bytes = data.total_transmitted();
+            for data in &networks {
+                if data.name != "lo" {
+                    primary_interface = Some(data.name.clone());
+                    prev_rx_bytes = data.received;
+                    prev_tx_bytes = data.transmitted;
                     break;
                 }
             }
@@ -49,32 +58,32 @@ impl NetworkStats {
         }
 
         Self {
-            networks,
+            path,
             primary_interface,
             prev_rx_bytes,
             prev_tx_bytes,
+            prev_sample: Instant::now(),
             rx_bytes_per_sec: 0,
             tx_bytes_per_sec: 0,
         }
     }
 
     pub fn update(&mut self) {
-        self.networks.refresh(false);
+        let networks = read_counters(&self.path);
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.prev_sample).as_secs_f64();
+        self.prev_sample = now;
 
         if let Some(ref interface_name) = self.primary_interface {
-            if let Some(data) = self
-                .networks
-                .iter()
-                .find(|(name, _)| *name == interface_name)
-                .map(|(_, data)| data)
-            {
-                let current_rx = data.total_received();
-                let current_tx = data.total_transmitted();
-
-                // Calculate bytes per second (updates happen every 1 second)
+            if let Some(data) = networks.iter().find(|data| data.name == *interface_name) {
+                let current_rx = data.received;
+                let current_tx = data.transmitted;
+
+                // Scale by the time since the last sample, which depends on the interval
                 // Use saturating_sub to handle counter wraparound
-                self.rx_bytes_per_sec = current_rx.saturating_sub(self.prev_rx_bytes);
-                self.tx_bytes_per_sec = current_tx.saturating_sub(self.prev_tx_bytes);
+                self.rx_bytes_per_sec = per_second(current_rx.saturating_sub(self.prev_rx_bytes), elapsed);
+                self.tx_bytes_per_sec = per_second(current_tx.saturating_sub(self.prev_tx_bytes), elapsed);
 
                 // Update previous values for next tick
                 self.prev_rx_bytes = current_rx;
@@ -91,6 +100,19 @@ impl NetworkStats {
         }
     }
 
+    pub fn interface(&self) -> Option<&str> {
+        self.primary_interface.as_deref()
+    }
+
+    /// Bytes received on the interface since boot
+    pub fn received_bytes(&self) -> u64 {
+        self.prev_rx_bytes
+    }
+
+    pub fn transmitted_bytes(&self) -> u64 {
+        self.prev_tx_bytes
+    }
+
     pub fn download_bps(&self) -> u64 {
         self.rx_bytes_per_sec
     }
@@ -99,3 +121,77 @@ impl NetworkStats {
         self.tx_bytes_per_sec
     }
 }
+
+/// Byte counters of one interface since boot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counters {
+    pub name: String,
+    pub received: u64,
+    pub transmitted: u64,
+}
+
+fn read_counters(path: &Path) -> Vec<Counters> {
+    fs::read_to_string(path)
+        .map(|contents| parse_net_dev(&contents))
+        .unwrap_or_default()
+}
+
+/// Parse `/proc/net/dev`, keeping the kernel's interface order
+///
+/// After two header lines each row is `name: ` then eight receive and eight
+/// transmit columns, the first of each being bytes.
+pub fn parse_net_dev(contents: &str) -> Vec<Counters> {
+    contents
+        .lines()
+        .skip(2)
+        .filter_map(|line| {
+            let (name, columns) = line.split_once(':')?;
+            let columns: Vec<&str> = columns.split_whitespace().collect();
+            Some(Counters {
+                name: name.trim().to_string(),
+                received: columns.first()?.parse().ok()?,
+                transmitted: columns.get(8)?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+fn per_second(bytes: u64, elapsed_secs: f64) -> u64 {
+    if elapsed_secs <= 0.0 {
+        return 0;
+    }
+    (bytes as f64 / elapsed_secs).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_byte_columns_in_order() {
+        let net_dev = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:  123456     789    0    0    0     0          0         0   123456     789    0    0    0     0       0          0
+enp5s0: 98765432  65432    0    0    0     0          0       120  1234567   9876    0    0    0     0       0          0
+";
+        assert_eq!(
+            parse_net_dev(net_dev),
+            [
+                Counters { name: "lo".into(), received: 123456, transmitted: 123456 },
+                Counters { name: "enp5s0".into(), received: 98765432, transmitted: 1234567 },
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_short_rows() {
+        assert_eq!(parse_net_dev("header\nheader\n  eth0: 1 2 3\n"), []);
+    }
+
+    #[test]
+    fn rate_scales_by_elapsed_time() {
+        assert_eq!(per_second(3000, 2.0), 1500);
+        assert_eq!(per_second(3000, 0.0), 0);
+    }
+}
@@ -0,0 +1,251 @@
+//! When each monitor is due for a fresh sample
+//!
+//! Every monitor has its own interval. The applet ticks at the greatest
+//! common divisor of the enabled ones and samples only what is due.
+
+use std::time::{Duration, Instant};
+
+use crate::config::validate::REFRESH_INTERVAL_MIN_MS;
+use crate::config::{AlertMetric, Config};
+
+/// Disks fill slowly, so free space is checked far less often than anything shown
+const DISK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Cpu,
+    Memory,
+    Network,
+    /// CPU and GPU temperature, read together
+    Temperature,
+    /// Free disk space, for alert rules only
+    Disk,
+}
+
+impl Source {
+    pub const ALL: [Source; 5] = [
+        Source::Cpu,
+        Source::Memory,
+        Source::Network,
+        Source::Temperature,
+        Source::Disk,
+    ];
+
+    /// Whether any enabled monitor reads from this source
+    pub fn enabled(self, config: &Config) -> bool {
+        let toggles = &config.monitors;
+        match self {
+            Source::Cpu => toggles.cpu_usage,
+            Source::Memory => toggles.memory,
+            Source::Network => toggles.network,
+            Source::Temperature => toggles.cpu_temperature || toggles.gpu_temperature,
+            Source::Disk => config.alerts.iter().any(|rule| rule.metric == AlertMetric::DiskFree),
+        }
+    }
+
+    pub fn interval(self, config: &Config) -> Duration {
+        let intervals = &config.intervals;
+        let ms = match self {
+            Source::Cpu => intervals.cpu_ms,
+            Source::Memory => intervals.memory_ms,
+            Source::Network => intervals.network_ms,
+            Source::Temperature => intervals.temperature_ms,
+            Source::Disk => return DISK_INTERVAL,
+        };
+        Duration::from_millis(ms.unwrap_or(config.refresh_interval_ms))
+    }
+}
+
+/// Time between ticks: the largest period every enabled interval is a multiple of
+///
+/// Intervals with no useful common divisor (999 and 1000 ms) would tick every
+/// millisecond, so the tick never drops below the shortest valid interval;
+/// the slack in `Schedule::take_due` absorbs the difference.
+pub fn tick(config: &Config) -> Duration {
+    let ms = Source::ALL
+        .into_iter()
+        .filter(|source| source.enabled(config))
+        .map(|source| source.interval(config).as_millis() as u64)
+        .reduce(gcd)
+        .unwrap_or(config.refresh_interval_ms);
+
+    Duration::from_millis(ms.max(REFRESH_INTERVAL_MIN_MS))
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Last sample time of each source
+#[derive(Debug)]
+pub struct Schedule {
+    last: [Option<Instant>; Source::ALL.len()],
+    /// Multiplier on every interval, raised by adaptive refresh
+    pace: u32,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            last: [None; Source::ALL.len()],
+            pace: 1,
+        }
+    }
+}
+
+impl Schedule {
+    pub fn set_pace(&mut self, pace: u32) {
+        self.pace = pace.max(1);
+    }
+
+    /// Time between ticks at the current pace
+    pub fn tick(&self, config: &Config) -> Duration {
+        tick(config) * self.pace
+    }
+
+    /// Whether `source` should be sampled at `now`, recording the sample if so
+    ///
+    /// Ticks arrive a little early or late, so anything within half a tick of
+    /// its interval counts as due rather than waiting a whole extra tick.
+    pub fn take_due(&mut self, source: Source, config: &Config, now: Instant) -> bool {
+        let last = &mut self.last[source as usize];
+        let slack = tick(config) * self.pace / 2;
+        let interval = source.interval(config) * self.pace;
+
+        let due = last.is_none_or(|last| now + slack >= last + interval);
+        if due {
+            *last = Some(now);
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AlertCondition, AlertRule};
+
+    fn config(cpu_ms: u64, memory_ms: u64) -> Config {
+        let mut config = Config::default();
+        config.intervals.cpu_ms = Some(cpu_ms);
+        config.intervals.memory_ms = Some(memory_ms);
+        config.intervals.network_ms = Some(cpu_ms);
+        config.intervals.temperature_ms = Some(memory_ms);
+        config
+    }
+
+    #[test]
+    fn tick_is_common_divisor_of_enabled_sources() {
+        assert_eq!(tick(&config(1000, 1500)), Duration::from_millis(500));
+        assert_eq!(tick(&Config::default()), Duration::from_secs(1));
+
+        let mut config = config(1000, 1500);
+        config.monitors.memory = false;
+        config.monitors.cpu_temperature = false;
+        config.monitors.gpu_temperature = false;
+        assert_eq!(tick(&config), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn tick_never_drops_below_shortest_interval() {
+        assert_eq!(tick(&config(999, 1000)), Duration::from_millis(REFRESH_INTERVAL_MIN_MS));
+        assert_eq!(tick(&config(150, 100)), Duration::from_millis(REFRESH_INTERVAL_MIN_MS));
+    }
+
+    #[test]
+    fn tick_with_nothing_enabled_follows_refresh_interval() {
+        let mut config = Config { refresh_interval_ms: 2000, ..Config::default() };
+        config.monitors.cpu_usage = false;
+        config.monitors.cpu_temperature = false;
+        config.monitors.gpu_temperature = false;
+        config.monitors.memory = false;
+        config.monitors.network = false;
+
+        assert_eq!(tick(&config), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn disk_is_only_sampled_for_alerts() {
+        let mut config = Config::default();
+        assert!(!Source::Disk.enabled(&config));
+
+        config.alerts.push(AlertRule {
+            metric: AlertMetric::DiskFree,
+            condition: AlertCondition::Below,
+            threshold: 10.0,
+            for_secs: 0,
+            cooldown_secs: 300,
+            notify_recovery: true,
+        });
+        assert!(Source::Disk.enabled(&config));
+        assert_eq!(tick(&config), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn take_due_waits_for_each_interval() {
+        let config = config(1000, 3000);
+        let start = Instant::now();
+        let mut schedule = Schedule::default();
+
+        assert!(schedule.take_due(Source::Cpu, &config, start));
+        assert!(schedule.take_due(Source::Memory, &config, start));
+
+        let second = start + Duration::from_secs(1);
+        assert!(schedule.take_due(Source::Cpu, &config, second));
+        assert!(!schedule.take_due(Source::Memory, &config, second));
+
+        let third = start + Duration::from_secs(3);
+        assert!(schedule.take_due(Source::Cpu, &config, third));
+        assert!(schedule.take_due(Source::Memory, &config, third));
+    }
+
+    #[test]
+    fn take_due_allows_half_a_tick_of_slack() {
+        let config = config(1000, 1000);
+        let start = Instant::now();
+        let mut schedule = Schedule::default();
+        schedule.take_due(Source::Cpu, &config, start);
+
+        assert!(!schedule.take_due(Source::Cpu, &config, start + Duration::from_millis(400)));
+        assert!(schedule.take_due(Source::Cpu, &config, start + Duration::from_millis(600)));
+    }
+
+    #[test]
+    fn take_due_copes_with_intervals_off_the_tick() {
+        let config = config(999, 1000);
+        let tick = tick(&config);
+        let start = Instant::now();
+        let mut schedule = Schedule::default();
+        schedule.take_due(Source::Cpu, &config, start);
+
+        let mut now = start;
+        let mut samples = 0;
+        while now < start + Duration::from_secs(10) {
+            now += tick;
+            if schedule.take_due(Source::Cpu, &config, now) {
+                samples += 1;
+            }
+        }
+        assert_eq!(samples, 10);
+    }
+
+    #[test]
+    fn pace_stretches_ticks_and_intervals() {
+        let config = config(1000, 1000);
+        let start = Instant::now();
+        let mut schedule = Schedule::default();
+        schedule.set_pace(4);
+
+        assert_eq!(schedule.tick(&config), Duration::from_secs(4));
+        schedule.take_due(Source::Cpu, &config, start);
+        assert!(!schedule.take_due(Source::Cpu, &config, start + Duration::from_secs(1)));
+        assert!(schedule.take_due(Source::Cpu, &config, start + Duration::from_secs(4)));
+
+        schedule.set_pace(0);
+        assert_eq!(schedule.tick(&config), Duration::from_secs(1));
+    }
+}
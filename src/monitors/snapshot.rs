@@ -0,0 +1,63 @@
+//! Immutable readings handed from the collector to the applet
+
+use serde::{Deserialize, Serialize};
+
+/// Every value the monitors produced on one tick
+///
+/// Fields added after recordings were introduced default when missing, so
+/// older recordings still replay.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub cpu_usage: f32,
+    pub cpu_celsius: Option<f32>,
+    /// Label of the sensor `cpu_celsius` was read from
+    #[serde(default)]
+    pub cpu_sensor: Option<String>,
+    /// sysfs file behind `cpu_sensor`
+    #[serde(default)]
+    pub cpu_sensor_path: Option<String>,
+    /// Hottest GPU
+    pub gpu_celsius: Option<f32>,
+    #[serde(default)]
+    pub gpus: Vec<GpuReading>,
+    pub memory_used_bytes: u64,
+    pub memory_total_bytes: u64,
+    /// Interface the network rates are measured on
+    #[serde(default)]
+    pub interface: Option<String>,
+    pub download_bps: u64,
+    pub upload_bps: u64,
+    /// Bytes moved on `interface` since boot
+    #[serde(default)]
+    pub received_bytes: u64,
+    #[serde(default)]
+    pub transmitted_bytes: u64,
+    /// Free space on the fullest disk, only sampled while an alert rule uses it
+    #[serde(default)]
+    pub disk_free_percent: Option<f32>,
+    /// Where the disk behind `disk_free_percent` is mounted
+    #[serde(default)]
+    pub disk_mount: Option<String>,
+}
+
+/// Temperature of one GPU
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuReading {
+    /// DRM card (`card1`) or NVIDIA index (`nvidia0`)
+    pub gpu: String,
+    /// Driver or model name
+    pub device: String,
+    pub celsius: f32,
+    /// hwmon file the temperature was read from; `None` for nvidia-smi
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+impl Snapshot {
+    pub fn memory_usage_percent(&self) -> f32 {
+        if self.memory_total_bytes == 0 {
+            return 0.0;
+        }
+        self.memory_used_bytes as f32 / self.memory_total_bytes as f32 * 100.0
+    }
+}
@@ -1,114 +1,314 @@
-use sysinfo::Components;
 use std::fs;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::GpuReading;
+use crate::sysfs::Root;
+
+/// How long a GPU reading may take before we carry on with the previous value
+const GPU_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// nvidia-smi is killed if it hasn't answered by then
+const NVIDIA_SMI_TIMEOUT: Duration = Duration::from_millis(1500);
+
+const HWMON: &str = "/sys/class/hwmon";
+const THERMAL: &str = "/sys/class/thermal";
+
+/// hwmon drivers that only report CPU temperatures
+const CPU_DRIVERS: &[&str] = &["k10temp", "coretemp", "zenpower", "cpu_thermal"];
 
 pub struct TemperatureStats {
-    components: Components,
-    gpu_temp_celsius: Option<f32>,
+    cpu: Option<CpuSensor>,
+    cpu_celsius: Option<f32>,
+    gpu: GpuReader,
+    gpus: Vec<GpuReading>,
+}
+
+/// The file the CPU temperature is read from, found once at startup
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpuSensor {
+    /// Driver and sensor label, e.g. "k10temp Tctl"
+    pub label: String,
+    pub path: PathBuf,
+}
+
+/// Reads GPU sensors on their own thread, since waking a sleeping card or a
+/// wedged driver can block for seconds
+struct GpuReader {
+    requests: Sender<()>,
+    readings: Receiver<Vec<GpuReading>>,
+    /// An earlier request timed out and hasn't answered yet
+    busy: bool,
 }
 
 impl TemperatureStats {
-    pub fn new() -> Self {
-        let components = Components::new_with_refreshed_list();
+    pub fn new(root: &Root) -> Self {
+        Self::with_nvidia_smi(root, nvidia_smi(root))
+    }
+
+    /// Like `new`, running `nvidia_smi` for NVIDIA cards, or skipping them if `None`
+    pub fn with_nvidia_smi(root: &Root, nvidia_smi: Option<PathBuf>) -> Self {
+        let cpu = find_cpu_sensor(root);
         let mut stats = Self {
-            components,
-            gpu_temp_celsius: None,
+            cpu_celsius: cpu.as_ref().and_then(|sensor| read_celsius(&sensor.path)),
+            cpu,
+            gpu: GpuReader::spawn(root.clone(), nvidia_smi),
+            gpus: Vec::new(),
         };
 
-        if let Some(temp) = stats.cpu_celsius() {
-            log::info!("CPU temperature sensor detected: {:.1}°C", temp);
-        } else {
-            log::warn!("No CPU temperature sensor found (searched: cpu, tdie, tctl, core labels)");
+        match (&stats.cpu, stats.cpu_celsius) {
+            (Some(sensor), Some(temp)) => log::info!(
+                "CPU temperature sensor detected: {} ({}) {:.1}°C",
+                sensor.label,
+                sensor.path.display(),
+                temp
+            ),
+            _ => log::warn!("No CPU temperature sensor found (searched: cpu, tdie, tctl, core labels)"),
         }
 
         stats.update_gpu();
 
-        if let Some(temp) = stats.gpu_temp_celsius {
-            log::info!("GPU temperature sensor detected: {:.1}°C", temp);
+        for gpu in &stats.gpus {
+            log::info!("GPU temperature sensor detected: {} ({}) {:.1}°C", gpu.gpu, gpu.device, gpu.celsius);
         }
 
         stats
     }
 
     pub fn update(&mut self) {
-        self.components.refresh(false);
+        self.cpu_celsius = self.cpu.as_ref().and_then(|sensor| read_celsius(&sensor.path));
         self.update_gpu();
     }
 
     pub fn cpu_celsius(&self) -> Option<f32> {
-        self.components.iter().find_map(|component| {
-            let label = component.label().to_lowercase();
-            if label.contains("cpu")
-                || label.contains("tdie")
-                || label.contains("tctl")
-                || label.starts_with("core")
-            {
-                component.temperature()
-            } else {
-                None
-            }
-        })
+        self.cpu_celsius
+    }
+
+    /// Label of the sensor the CPU temperature comes from
+    pub fn cpu_sensor(&self) -> Option<String> {
+        Some(self.cpu.as_ref()?.label.clone())
+    }
+
+    /// sysfs file the CPU temperature comes from
+    pub fn cpu_sensor_path(&self) -> Option<String> {
+        Some(self.cpu.as_ref()?.path.display().to_string())
     }
 
+    /// Temperature of the hottest GPU
     pub fn gpu_celsius(&self) -> Option<f32> {
-        self.gpu_temp_celsius
+        self.gpus.iter().map(|gpu| gpu.celsius).reduce(f32::max)
+    }
+
+    pub fn gpus(&self) -> &[GpuReading] {
+        &self.gpus
     }
 
     fn update_gpu(&mut self) {
-        let mut max_temp = None;
+        if let Some(reading) = self.gpu.read() {
+            self.gpus = reading;
+        }
+    }
+}
+
+/// The first readable CPU sensor: hwmon in name order, then thermal zones
+pub fn find_cpu_sensor(root: &Root) -> Option<CpuSensor> {
+    let hwmon = sorted_entries(&root.join(HWMON)).into_iter().flat_map(|hwmon| {
+        let driver = read_trimmed(&hwmon.join("name")).unwrap_or_default();
+        hwmon_inputs(&hwmon).into_iter().map(move |(input, label)| {
+            let label = match label {
+                Some(label) => format!("{} {}", driver, label),
+                None => driver.clone(),
+            };
+            (driver.clone(), label, input)
+        })
+    });
+    let from_hwmon = hwmon
+        .filter(|(driver, label, _)| CPU_DRIVERS.contains(&driver.as_str()) || is_cpu_label(label))
+        .map(|(_, label, path)| CpuSensor { label, path });
+
+    let from_thermal = sorted_entries(&root.join(THERMAL))
+        .into_iter()
+        .filter(|zone| {
+            let name = zone.file_name().map(|name| name.to_string_lossy().into_owned());
+            name.is_some_and(|name| name.starts_with("thermal_zone"))
+        })
+        .filter_map(|zone| {
+            let label = read_trimmed(&zone.join("type"))?;
+            is_cpu_label(&label).then(|| CpuSensor { label, path: zone.join("temp") })
+        });
+
+    from_hwmon
+        .chain(from_thermal)
+        .find(|sensor| read_celsius(&sensor.path).is_some())
+}
+
+fn is_cpu_label(label: &str) -> bool {
+    let label = label.to_lowercase();
+    ["cpu", "tdie", "tctl", "package id", "x86_pkg_temp"]
+        .iter()
+        .any(|name| label.contains(name))
+        || label.split_whitespace().any(|word| word == "core")
+}
+
+/// `tempN_input` files of one hwmon device in index order, with their labels
+fn hwmon_inputs(hwmon: &Path) -> Vec<(PathBuf, Option<String>)> {
+    let mut inputs: Vec<(u32, PathBuf, Option<String>)> = sorted_entries(hwmon)
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?;
+            let index = name.strip_prefix("temp")?.strip_suffix("_input")?.parse().ok()?;
+            let label = read_trimmed(&hwmon.join(format!("temp{}_label", index)));
+            Some((index, path, label))
+        })
+        .collect();
+    inputs.sort_by_key(|(index, _, _)| *index);
+    inputs.into_iter().map(|(_, path, label)| (path, label)).collect()
+}
+
+fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    entries.sort();
+    entries
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    Some(fs::read_to_string(path).ok()?.trim().to_string())
+}
+
+/// A sysfs temperature, which is in millidegrees Celsius
+fn read_celsius(path: &Path) -> Option<f32> {
+    let millidegrees: i32 = read_trimmed(path)?.parse().ok()?;
+    Some(millidegrees as f32 / 1000.0)
+}
 
-        // Detect AMD/Intel GPUs via sysfs
-        if let Some(temp) = self.detect_sysfs_gpu_temp() {
-            max_temp = Some(max_temp.map_or(temp, |t| f32::max(t, temp)));
+/// nvidia-smi from `PATH` on the live system, or the root's `/usr/bin/nvidia-smi` for a fixture
+fn nvidia_smi(root: &Root) -> Option<PathBuf> {
+    if root.is_live() {
+        return Some(PathBuf::from("nvidia-smi"));
+    }
+    Some(root.join("/usr/bin/nvidia-smi")).filter(|path| path.exists())
+}
+
+impl GpuReader {
+    fn spawn(root: Root, nvidia_smi: Option<PathBuf>) -> Self {
+        let (requests, pending) = mpsc::channel::<()>();
+        let (results, readings) = mpsc::channel();
+
+        let spawned = thread::Builder::new()
+            .name("gpu-temperature".into())
+            .spawn(move || {
+                for () in pending {
+                    if results.send(read_gpu_temp(&root, nvidia_smi.as_deref())).is_err() {
+                        break;
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            log::warn!("Failed to start GPU temperature reader: {}", e);
+        }
+
+        Self {
+            requests,
+            readings,
+            busy: false,
+        }
+    }
+
+    /// The latest reading, or `None` if the sensors didn't answer in time
+    fn read(&mut self) -> Option<Vec<GpuReading>> {
+        if self.busy {
+            // Don't queue up behind a stuck read or wait on it again
+            let reading = self.readings.try_recv().ok()?;
+            self.busy = false;
+            return Some(reading);
         }
 
-        // Detect Nvidia GPUs via nvidia-smi
-        if let Some(temp) = self.detect_nvidia_gpu_temp() {
-            max_temp = Some(max_temp.map_or(temp, |t| f32::max(t, temp)));
+        self.requests.send(()).ok()?;
+        match self.readings.recv_timeout(GPU_TIMEOUT) {
+            Ok(reading) => Some(reading),
+            Err(_) => {
+                log::warn!(
+                    "GPU temperature sensors didn't answer within {:?}; keeping the last reading",
+                    GPU_TIMEOUT
+                );
+                self.busy = true;
+                None
+            }
         }
+    }
+}
 
-        self.gpu_temp_celsius = max_temp;
+fn read_gpu_temp(root: &Root, nvidia_smi: Option<&Path>) -> Vec<GpuReading> {
+    // AMD/Intel GPUs via sysfs, then Nvidia GPUs via nvidia-smi
+    let mut gpus = detect_sysfs_gpu_temps(root);
+    if let Some(command) = nvidia_smi {
+        gpus.extend(detect_nvidia_gpu_temps(command));
     }
+    gpus
+}
 
-    fn detect_sysfs_gpu_temp(&self) -> Option<f32> {
-        if let Ok(entries) = fs::read_dir("/sys/class/drm") {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                let name = entry.file_name();
-                let name_str = name.to_string_lossy();
+fn detect_sysfs_gpu_temps(root: &Root) -> Vec<GpuReading> {
+    let mut gpus = Vec::new();
 
-                if !name_str.starts_with("card") || name_str.contains('-') {
-                    continue;
-                }
+    if let Ok(entries) = fs::read_dir(root.join("/sys/class/drm")) {
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name_str = name.to_string_lossy();
 
-                let hwmon_path = path.join("device/hwmon");
-                if let Ok(hwmon_entries) = fs::read_dir(&hwmon_path) {
-                    for hwmon_entry in hwmon_entries.flatten() {
-                        let hwmon_name = hwmon_entry.file_name();
-                        if !hwmon_name.to_string_lossy().starts_with("hwmon") {
-                            continue;
-                        }
+            if !name_str.starts_with("card") || name_str.contains('-') {
+                continue;
+            }
 
-                        let temp_path = hwmon_entry.path();
-
-                        // Look for edge temperature
-                        if let Ok(temp_entries) = fs::read_dir(&temp_path) {
-                            for temp_entry in temp_entries.flatten() {
-                                let temp_file = temp_entry.file_name();
-                                let temp_file_str = temp_file.to_string_lossy();
-
-                                if temp_file_str.starts_with("temp") && temp_file_str.ends_with("_label") {
-                                    if let Ok(label) = fs::read_to_string(temp_entry.path()) {
-                                        if label.trim() == "edge" {
-                                            let input_file = temp_file_str.replace("_label", "_input");
-                                            let input_path = temp_path.join(&input_file);
-                                            if let Ok(temp_str) = fs::read_to_string(&input_path) {
-                                                if let Ok(temp_millidegrees) = temp_str.trim().parse::<i32>() {
-                                                    return Some(temp_millidegrees as f32 / 1000.0);
-                                                }
-                                            }
-                                        }
-                                    }
+            if let Some((device, celsius, path)) = card_edge_temp(&entry.path()) {
+                gpus.push(GpuReading {
+                    gpu: name_str.into_owned(),
+                    device,
+                    celsius,
+                    path: Some(path.display().to_string()),
+                });
+            }
+        }
+    }
+
+    // read_dir order is arbitrary; keep card0 before card1
+    gpus.sort_by(|a, b| a.gpu.cmp(&b.gpu));
+    gpus
+}
+
+/// Driver name, edge temperature and the file it was read from for one DRM card
+fn card_edge_temp(card: &Path) -> Option<(String, f32, PathBuf)> {
+    let hwmon_entries = fs::read_dir(card.join("device/hwmon")).ok()?;
+    for hwmon_entry in hwmon_entries.flatten() {
+        let hwmon_name = hwmon_entry.file_name();
+        if !hwmon_name.to_string_lossy().starts_with("hwmon") {
+            continue;
+        }
+
+        let temp_path = hwmon_entry.path();
+
+        // Look for edge temperature
+        if let Ok(temp_entries) = fs::read_dir(&temp_path) {
+            for temp_entry in temp_entries.flatten() {
+                let temp_file = temp_entry.file_name();
+                let temp_file_str = temp_file.to_string_lossy();
+
+                if temp_file_str.starts_with("temp") && temp_file_str.ends_with("_label") {
+                    if let Ok(label) = fs::read_to_string(temp_entry.path()) {
+                        if label.trim() == "edge" {
+                            let input_file = temp_file_str.replace("_label", "_input");
+                            let input_path = temp_path.join(&input_file);
+                            if let Ok(temp_str) = fs::read_to_string(&input_path) {
+                                if let Ok(temp_millidegrees) = temp_str.trim().parse::<i32>() {
+                                    let device = fs::read_to_string(temp_path.join("name"))
+                                        .map(|name| name.trim().to_string())
+                                        .unwrap_or_else(|_| "unknown".to_string());
+                                    let celsius = temp_millidegrees as f32 / 1000.0;
+                                    return Some((device, celsius, input_path));
                                 }
                             }
                         }
@@ -116,30 +316,61 @@ impl TemperatureStats {
                 }
             }
         }
-
-        None
     }
 
-    fn detect_nvidia_gpu_temp(&self) -> Option<f32> {
-        let output = Command::new("nvidia-smi")
-            .args(["--query-gpu=temperature.gpu", "--format=csv,noheader,nounits"])
-            .output();
+    None
+}
 
-        if let Ok(output) = output {
-            if output.status.success() {
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let mut max_temp = None;
+fn detect_nvidia_gpu_temps(command: &Path) -> Vec<GpuReading> {
+    let Ok(mut child) = Command::new(command)
+        .args(["--query-gpu=index,name,temperature.gpu", "--format=csv,noheader,nounits"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return Vec::new();
+    };
 
-                for line in stdout.lines() {
-                    if let Ok(temp) = line.trim().parse::<f32>() {
-                        max_temp = Some(max_temp.map_or(temp, |t| f32::max(t, temp)));
-                    }
-                }
-
-                return max_temp;
+    // A wedged driver can leave nvidia-smi hanging indefinitely
+    let deadline = Instant::now() + NVIDIA_SMI_TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+            _ => {
+                log::warn!("nvidia-smi didn't finish within {:?}", NVIDIA_SMI_TIMEOUT);
+                let _ = child.kill();
+                let _ = child.wait();
+                return Vec::new();
             }
         }
+    }
 
-        None
+    let Ok(output) = child.wait_with_output() else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
     }
+
+    parse_nvidia_smi(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Each line is "<index>, <name>, <temperature>"
+pub fn parse_nvidia_smi(output: &str) -> Vec<GpuReading> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(',').map(str::trim);
+            let index = fields.next()?;
+            let name = fields.next()?;
+            let celsius = fields.next()?.parse::<f32>().ok()?;
+            Some(GpuReading {
+                gpu: format!("nvidia{}", index),
+                device: name.to_string(),
+                celsius,
+                path: None,
+            })
+        })
+        .collect()
 }
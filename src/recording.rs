@@ -0,0 +1,99 @@
+//! Saving snapshots to a file and playing them back
+//!
+//! Recordings are JSON Lines: one object per sample holding the snapshot's
+//! fields plus `time_ms`, the wall-clock time in milliseconds since the Unix
+//! epoch. Replay keeps the original spacing between samples.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::monitors::Snapshot;
+
+/// A snapshot with the wall-clock time it was taken
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub time_ms: u64,
+    #[serde(flatten)]
+    pub snapshot: Snapshot,
+}
+
+impl Record {
+    pub fn new(snapshot: Snapshot, time: SystemTime) -> Self {
+        let time_ms = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64);
+        Self { time_ms, snapshot }
+    }
+}
+
+pub struct Recorder {
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        // Flushed per line so a crash still leaves everything up to it
+        self.writer.flush()
+    }
+}
+
+pub struct Replay {
+    lines: io::Lines<BufReader<File>>,
+    line_number: usize,
+    previous_ms: Option<u64>,
+}
+
+impl Replay {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            lines: BufReader::new(File::open(path)?).lines(),
+            line_number: 0,
+            previous_ms: None,
+        })
+    }
+}
+
+impl Iterator for Replay {
+    /// How long after the previous sample this one was taken, and the sample
+    type Item = io::Result<(Duration, Snapshot)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            self.line_number += 1;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let record: Record = match serde_json::from_str(&line) {
+                Ok(record) => record,
+                Err(e) => {
+                    return Some(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: {}", self.line_number, e),
+                    )))
+                }
+            };
+
+            let delay = self
+                .previous_ms
+                .map_or(0, |previous| record.time_ms.saturating_sub(previous));
+            self.previous_ms = Some(record.time_ms);
+
+            return Some(Ok((Duration::from_millis(delay), record.snapshot)));
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! Long-running logs of every sample
+//!
+//! Samples are appended to `samples-<unix ms>.csv` (or `.influx`) files in the
+//! state directory. A new file starts when the current one grows too large or
+//! too old, or when the enabled monitors change its columns, and files beyond
+//! `keep_files` are deleted oldest first.
+
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::{LogFormat, MonitorToggles, SampleLog as Settings};
+use crate::monitors::Snapshot;
+
+const FILE_PREFIX: &str = "samples-";
+
+/// Influx measurement every line is written to
+const MEASUREMENT: &str = "systemstats";
+
+pub struct SampleLog {
+    dir: Option<PathBuf>,
+    current: Option<Current>,
+    /// Settings the last write failed with; nothing is written until they change
+    failed: Option<Settings>,
+}
+
+struct Current {
+    writer: BufWriter<File>,
+    format: LogFormat,
+    columns: Vec<Column>,
+    size: u64,
+    opened: SystemTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    CpuUsage,
+    CpuTemperature,
+    GpuTemperature,
+    MemoryUsed,
+    MemoryTotal,
+    Download,
+    Upload,
+}
+
+impl Column {
+    /// Columns for the monitors that are turned on, in a fixed order
+    fn enabled(monitors: &MonitorToggles) -> Vec<Column> {
+        let mut columns = Vec::new();
+        if monitors.cpu_usage {
+            columns.push(Column::CpuUsage);
+        }
+        if monitors.cpu_temperature {
+            columns.push(Column::CpuTemperature);
+        }
+        if monitors.gpu_temperature {
+            columns.push(Column::GpuTemperature);
+        }
+        if monitors.memory {
+            columns.extend([Column::MemoryUsed, Column::MemoryTotal]);
+        }
+        if monitors.network {
+            columns.extend([Column::Download, Column::Upload]);
+        }
+        columns
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Column::CpuUsage => "cpu_usage_percent",
+            Column::CpuTemperature => "cpu_celsius",
+            Column::GpuTemperature => "gpu_celsius",
+            Column::MemoryUsed => "memory_used_bytes",
+            Column::MemoryTotal => "memory_total_bytes",
+            Column::Download => "download_bytes_per_second",
+            Column::Upload => "upload_bytes_per_second",
+        }
+    }
+
+    /// The value as written, `None` when its sensor is missing
+    fn value(self, stats: &Snapshot) -> Option<Value> {
+        match self {
+            Column::CpuUsage => Some(Value::Float(stats.cpu_usage)),
+            Column::CpuTemperature => stats.cpu_celsius.map(Value::Float),
+            Column::GpuTemperature => stats.gpu_celsius.map(Value::Float),
+            Column::MemoryUsed => Some(Value::Integer(stats.memory_used_bytes)),
+            Column::MemoryTotal => Some(Value::Integer(stats.memory_total_bytes)),
+            Column::Download => Some(Value::Integer(stats.download_bps)),
+            Column::Upload => Some(Value::Integer(stats.upload_bps)),
+        }
+    }
+}
+
+enum Value {
+    Float(f32),
+    Integer(u64),
+}
+
+impl SampleLog {
+    /// Log into `dir`; without one, enabling the log fails on the first write
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            current: None,
+            failed: None,
+        }
+    }
+
+    /// Append one sample, rotating first if needed; a disabled log closes its file
+    ///
+    /// After an error the log stops until `settings` change, so a full disk
+    /// or unwritable directory is reported once rather than every sample.
+    pub fn write(
+        &mut self,
+        settings: &Settings,
+        monitors: &MonitorToggles,
+        snapshot: &Snapshot,
+        now: SystemTime,
+    ) -> io::Result<()> {
+        if self.failed.as_ref() == Some(settings) {
+            return Ok(());
+        }
+        self.failed = None;
+
+        let written = self.append(settings, monitors, snapshot, now);
+        if written.is_err() {
+            // Start over with a fresh file once the settings change
+            self.current = None;
+            self.failed = Some(settings.clone());
+        }
+        written
+    }
+
+    fn append(
+        &mut self,
+        settings: &Settings,
+        monitors: &MonitorToggles,
+        snapshot: &Snapshot,
+        now: SystemTime,
+    ) -> io::Result<()> {
+        if !settings.enabled {
+            self.current = None;
+            return Ok(());
+        }
+
+        let columns = Column::enabled(monitors);
+        let max_size = settings.max_size_mb.saturating_mul(1024 * 1024);
+        let max_age = Duration::from_secs(settings.max_age_hours.saturating_mul(60 * 60));
+        let rotate = self.current.as_ref().is_none_or(|current| {
+            current.format != settings.format
+                || current.columns != columns
+                || current.size >= max_size
+                || now.duration_since(current.opened).unwrap_or_default() >= max_age
+        });
+
+        if rotate {
+            // Close the old file before pruning so it's never deleted while open
+            self.current = None;
+            self.current = Some(self.open(settings.format, columns, now)?);
+            self.prune(settings.keep_files)?;
+        }
+
+        let Some(current) = &mut self.current else {
+            return Ok(());
+        };
+        let time_ms = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let line = match current.format {
+            LogFormat::Csv => csv_line(&current.columns, snapshot, time_ms),
+            LogFormat::Influx => match influx_line(&current.columns, snapshot, time_ms) {
+                Some(line) => line,
+                // Line protocol needs at least one field
+                None => return Ok(()),
+            },
+        };
+
+        current.writer.write_all(line.as_bytes())?;
+        // Flushed per line so a crash or power loss keeps everything up to it
+        current.writer.flush()?;
+        current.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn open(&self, format: LogFormat, columns: Vec<Column>, now: SystemTime) -> io::Result<Current> {
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no state directory to log into"))?;
+        fs::create_dir_all(dir)?;
+
+        let extension = match format {
+            LogFormat::Csv => "csv",
+            LogFormat::Influx => "influx",
+        };
+        let time_ms = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let path = dir.join(format!("{}{}.{}", FILE_PREFIX, time_ms, extension));
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let mut size = 0;
+        if format == LogFormat::Csv {
+            let mut header = String::from("time_ms");
+            for column in &columns {
+                header.push(',');
+                header.push_str(column.name());
+            }
+            header.push('\n');
+            writer.write_all(header.as_bytes())?;
+            size = header.len() as u64;
+        }
+
+        log::info!("Logging samples to {}", path.display());
+        Ok(Current {
+            writer,
+            format,
+            columns,
+            size,
+            opened: now,
+        })
+    }
+
+    /// Delete the oldest logs so at most `keep` remain
+    fn prune(&self, keep: u32) -> io::Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+
+        let mut logs: Vec<(u128, PathBuf)> = fs::read_dir(dir)?
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                let (stamp, _) = name.to_str()?.strip_prefix(FILE_PREFIX)?.split_once('.')?;
+                Some((stamp.parse().ok()?, entry.path()))
+            })
+            .collect();
+        logs.sort_unstable_by_key(|(stamp, _)| *stamp);
+
+        let excess = logs.len().saturating_sub(keep as usize);
+        for (_, path) in logs.into_iter().take(excess) {
+            fs::remove_file(&path)?;
+            log::debug!("Deleted old sample log {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Missing sensors leave their cell empty
+fn csv_line(columns: &[Column], snapshot: &Snapshot, time_ms: u64) -> String {
+    let mut line = time_ms.to_string();
+    for column in columns {
+        line.push(',');
+        match column.value(snapshot) {
+            Some(Value::Float(value)) => {
+                let _ = write!(line, "{:.1}", value);
+            }
+            Some(Value::Integer(value)) => {
+                let _ = write!(line, "{}", value);
+            }
+            None => {}
+        }
+    }
+    line.push('\n');
+    line
+}
+
+/// Missing sensors are left out; the interface is a tag when network rates are logged
+fn influx_line(columns: &[Column], snapshot: &Snapshot, time_ms: u64) -> Option<String> {
+    let mut fields = Vec::new();
+    for column in columns {
+        match column.value(snapshot) {
+            Some(Value::Float(value)) => fields.push(format!("{}={:.1}", column.name(), value)),
+            Some(Value::Integer(value)) => fields.push(format!("{}={}i", column.name(), value)),
+            None => {}
+        }
+    }
+    if fields.is_empty() {
+        return None;
+    }
+
+    let mut line = String::from(MEASUREMENT);
+    if let Some(interface) = &snapshot.interface {
+        if columns.contains(&Column::Download) {
+            let _ = write!(line, ",interface={}", escape_tag(interface));
+        }
+    }
+    // Line protocol timestamps are nanoseconds by default
+    let _ = writeln!(line, " {} {}", fields.join(","), u128::from(time_ms) * 1_000_000);
+    Some(line)
+}
+
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
+            enabled: true,
+            ..crate::config::Config::default().sample_log
+        }
+    }
+
+    fn monitors() -> MonitorToggles {
+        crate::config::Config::default().monitors
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            cpu_usage: 12.34,
+            cpu_celsius: Some(45.0),
+            memory_used_bytes: 4096,
+            memory_total_bytes: 8192,
+            interface: Some("eth 0".into()),
+            download_bps: 1000,
+            upload_bps: 10,
+            ..Snapshot::default()
+        }
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn logs(dir: &std::path::Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn csv_has_a_header_and_leaves_missing_sensors_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = SampleLog::new(Some(dir.path().to_path_buf()));
+
+        log.write(&settings(), &monitors(), &snapshot(), at(1)).unwrap();
+        log.write(&settings(), &monitors(), &snapshot(), at(2)).unwrap();
+
+        assert_eq!(logs(dir.path()), ["samples-1000.csv"]);
+        let contents = fs::read_to_string(dir.path().join("samples-1000.csv")).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(
+            lines[0],
+            "time_ms,cpu_usage_percent,cpu_celsius,gpu_celsius,memory_used_bytes,\
+             memory_total_bytes,download_bytes_per_second,upload_bytes_per_second"
+        );
+        assert_eq!(lines[1], "1000,12.3,45.0,,4096,8192,1000,10");
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn influx_tags_the_interface_and_skips_missing_sensors() {
+        let columns = Column::enabled(&monitors());
+        let line = influx_line(&columns, &snapshot(), 1000).unwrap();
+
+        assert_eq!(
+            line,
+            "systemstats,interface=eth\\ 0 cpu_usage_percent=12.3,cpu_celsius=45.0,\
+             memory_used_bytes=4096i,memory_total_bytes=8192i,download_bytes_per_second=1000i,\
+             upload_bytes_per_second=10i 1000000000\n"
+        );
+        assert_eq!(influx_line(&[Column::GpuTemperature], &snapshot(), 1000), None);
+    }
+
+    #[test]
+    fn rotates_on_age_and_columns_and_prunes_old_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = SampleLog::new(Some(dir.path().to_path_buf()));
+        let settings = Settings {
+            max_age_hours: 1,
+            keep_files: 2,
+            ..settings()
+        };
+
+        log.write(&settings, &monitors(), &snapshot(), at(0)).unwrap();
+        log.write(&settings, &monitors(), &snapshot(), at(3600)).unwrap();
+        assert_eq!(logs(dir.path()), ["samples-0.csv", "samples-3600000.csv"]);
+
+        let fewer = MonitorToggles {
+            network: false,
+            ..monitors()
+        };
+        log.write(&settings, &fewer, &snapshot(), at(3601)).unwrap();
+        assert_eq!(logs(dir.path()), ["samples-3600000.csv", "samples-3601000.csv"]);
+    }
+
+    #[test]
+    fn huge_limits_do_not_overflow() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = SampleLog::new(Some(dir.path().to_path_buf()));
+        let settings = Settings {
+            max_size_mb: u64::MAX,
+            max_age_hours: u64::MAX,
+            ..settings()
+        };
+
+        log.write(&settings, &monitors(), &snapshot(), at(1)).unwrap();
+        log.write(&settings, &monitors(), &snapshot(), at(2)).unwrap();
+        assert_eq!(logs(dir.path()).len(), 1);
+    }
+
+    #[test]
+    fn failure_stops_the_log_until_settings_change() {
+        let mut log = SampleLog::new(None);
+
+        assert!(log.write(&settings(), &monitors(), &snapshot(), at(1)).is_err());
+        assert!(log.write(&settings(), &monitors(), &snapshot(), at(2)).is_ok());
+
+        let influx = Settings {
+            format: LogFormat::Influx,
+            ..settings()
+        };
+        assert!(log.write(&influx, &monitors(), &snapshot(), at(3)).is_err());
+    }
+
+    #[test]
+    fn disabling_closes_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = SampleLog::new(Some(dir.path().to_path_buf()));
+        let disabled = Settings {
+            enabled: false,
+            ..settings()
+        };
+
+        log.write(&settings(), &monitors(), &snapshot(), at(1)).unwrap();
+        log.write(&disabled, &monitors(), &snapshot(), at(2)).unwrap();
+        assert!(log.current.is_none());
+
+        log.write(&settings(), &monitors(), &snapshot(), at(3)).unwrap();
+        assert_eq!(logs(dir.path()), ["samples-1000.csv", "samples-3000.csv"]);
+    }
+}
@@ -0,0 +1,296 @@
+//! Panel segments for the built-in layout
+//!
+//! Each segment carries the widest value it can plausibly display alongside
+//! the current one, so the view can reserve a stable width for it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, LabelStyle, NetworkUnit, Units};
+use crate::formatting::*;
+use crate::monitors::Snapshot;
+use crate::thresholds::ThresholdState;
+
+/// Hottest reading we reserve room for; three digits in every scale
+pub const WIDEST_TEMPERATURE_CELSIUS: f32 = 100.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentKind {
+    Cpu,
+    Gpu,
+    Memory,
+    Network,
+}
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub kind: SegmentKind,
+    pub label: &'static str,
+    pub style: LabelStyle,
+    pub state: ThresholdState,
+    pub value: String,
+    /// Value with abbreviated units for narrow layouts
+    pub short: String,
+    /// Longest value this segment can show with the current config
+    pub widest: String,
+}
+
+pub fn build(stats: &Snapshot, config: &Config) -> Vec<Segment> {
+    let toggles = &config.monitors;
+    let units = &config.units;
+    let display = &config.display;
+    let thresholds = &config.thresholds;
+    let mut segments = Vec::new();
+
+    // CPU
+    if toggles.cpu_usage || toggles.cpu_temperature {
+        let mut values = Vec::new();
+        let mut short = Vec::new();
+        let mut widest = Vec::new();
+        let mut state = ThresholdState::Normal;
+
+        if toggles.cpu_usage {
+            let usage = stats.cpu_usage;
+            state = state.max(ThresholdState::of(usage, &thresholds.cpu_usage));
+            values.push(format_percentage(usage, units));
+            short.push(format!("{:.0}%", usage.clamp(0.0, 100.0)));
+            widest.push(format_percentage(100.0, units));
+        }
+
+        if toggles.cpu_temperature {
+            if let Some(temp) = stats.cpu_celsius {
+                state = state.max(ThresholdState::of(temp, &thresholds.cpu_temperature));
+                values.push(format_temperature(temp, units));
+                short.push(format_temperature_short(temp, units));
+                widest.push(format_temperature(WIDEST_TEMPERATURE_CELSIUS, units));
+            }
+        }
+
+        if !values.is_empty() {
+            segments.push(Segment {
+                kind: SegmentKind::Cpu,
+                label: "CPU",
+                style: display.cpu,
+                state,
+                value: values.join(" | "),
+                short: short.join("\n"),
+                widest: widest.join(" | "),
+            });
+        }
+    }
+
+    // GPU temperature
+    if toggles.gpu_temperature {
+        if let Some(temp) = stats.gpu_celsius {
+            segments.push(Segment {
+                kind: SegmentKind::Gpu,
+                label: "GPU",
+                style: display.gpu,
+                state: ThresholdState::of(temp, &thresholds.gpu_temperature),
+                value: format_temperature(temp, units),
+                short: format_temperature_short(temp, units),
+                widest: format_temperature(WIDEST_TEMPERATURE_CELSIUS, units),
+            });
+        }
+    }
+
+    // Memory
+    if toggles.memory {
+        let total = stats.memory_total_bytes;
+        segments.push(Segment {
+            kind: SegmentKind::Memory,
+            label: "RAM",
+            style: display.memory,
+            state: ThresholdState::of(stats.memory_usage_percent(), &thresholds.memory_usage),
+            value: format!(
+                "{}/{}",
+                format_memory(stats.memory_used_bytes, units),
+                format_memory(total, units)
+            ),
+            short: format_memory_short(stats.memory_used_bytes, units),
+            widest: format!("{}/{}", format_memory(total, units), format_memory(total, units)),
+        });
+    }
+
+    // Network
+    if toggles.network {
+        let widest = widest_network_speed(units);
+        segments.push(Segment {
+            kind: SegmentKind::Network,
+            label: "Net",
+            style: display.network,
+            state: ThresholdState::Normal,
+            value: format!(
+                "↓{} ↑{}",
+                format_network_speed(stats.download_bps, units),
+                format_network_speed(stats.upload_bps, units)
+            ),
+            short: format!(
+                "↓{}\n↑{}",
+                format_network_speed_short(stats.download_bps, units),
+                format_network_speed_short(stats.upload_bps, units)
+            ),
+            widest: format!("↓{widest} ↑{widest}"),
+        });
+    }
+
+    segments
+}
+
+/// Everything known about one segment, unabbreviated, one fact per line
+pub fn details(kind: SegmentKind, stats: &Snapshot, config: &Config) -> String {
+    let toggles = &config.monitors;
+    let units = &config.units;
+    let temperature = |celsius: f32| {
+        let (value, label) = convert_temperature(celsius, units.temperature);
+        format!("{:.1}{}", value, label)
+    };
+    // Largest prefix that keeps the value at or above 1, up to giga
+    let bytes = |bytes: u64| {
+        let (divisor, label) = (0..=3)
+            .rev()
+            .map(|magnitude| memory_scale(units.memory, magnitude))
+            .find(|(divisor, _)| bytes as f64 >= *divisor)
+            .unwrap_or((1.0, "B"));
+        format!("{:.2} {}", bytes as f64 / divisor, label)
+    };
+    let mut lines = Vec::new();
+
+    match kind {
+        SegmentKind::Cpu => {
+            if toggles.cpu_usage {
+                lines.push(format!("Usage: {:.1}%", stats.cpu_usage));
+            }
+            if toggles.cpu_temperature {
+                if let Some(celsius) = stats.cpu_celsius {
+                    lines.push(format!("Temperature: {}", temperature(celsius)));
+                }
+                if let Some(sensor) = &stats.cpu_sensor {
+                    lines.push(format!("Sensor: {}", sensor));
+                }
+                if let Some(path) = &stats.cpu_sensor_path {
+                    lines.push(path.clone());
+                }
+            }
+        }
+        SegmentKind::Gpu => {
+            for gpu in &stats.gpus {
+                lines.push(format!("{} ({}): {}", gpu.gpu, gpu.device, temperature(gpu.celsius)));
+                if let Some(path) = &gpu.path {
+                    lines.push(path.clone());
+                }
+            }
+        }
+        SegmentKind::Memory => {
+            lines.push(format!(
+                "Used: {} of {} ({:.1}%)",
+                bytes(stats.memory_used_bytes),
+                bytes(stats.memory_total_bytes),
+                stats.memory_usage_percent()
+            ));
+        }
+        SegmentKind::Network => {
+            if let Some(interface) = &stats.interface {
+                lines.push(format!("Interface: {}", interface));
+            }
+            let speed = |bytes_per_sec| {
+                format_network_speed(bytes_per_sec, units).trim().to_string()
+            };
+            lines.push(format!("Download: {}", speed(stats.download_bps)));
+            lines.push(format!("Upload: {}", speed(stats.upload_bps)));
+            lines.push(format!("Received since boot: {}", bytes(stats.received_bytes)));
+            lines.push(format!("Sent since boot: {}", bytes(stats.transmitted_bytes)));
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// The panel line as plain text, for output outside the applet
+pub fn plain_line(stats: &Snapshot, config: &Config) -> String {
+    if let Some(template) = &config.format {
+        return template.render(stats, config);
+    }
+
+    build(stats, config)
+        .iter()
+        .map(|segment| format!("{}: {}", segment.label, segment.value))
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+fn widest_network_speed(units: &Units) -> String {
+    format_network_speed(widest_network_bps(units.network), units)
+}
+
+/// Widest speed is three integer digits plus a decimal below the gigabit/gigabyte step
+pub fn widest_network_bps(unit: NetworkUnit) -> u64 {
+    match unit {
+        NetworkUnit::Bits => 999_900_000 / 8,
+        NetworkUnit::Bytes => 999_900_000,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitors::MonitorStats;
+    use crate::sysfs::Root;
+    use std::path::Path;
+    use std::time::Instant;
+
+    /// The tooltip for `kind` after a first sample of a machine fixture
+    fn fixture_details(name: &str, kind: SegmentKind) -> (String, Snapshot) {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+        let config = Config::default();
+        let mut monitors = MonitorStats::new(&config, &Root::new(root));
+        monitors.update(&config, Instant::now());
+        let stats = monitors.snapshot();
+        (details(kind, &stats, &config), stats)
+    }
+
+    #[test]
+    fn cpu_details_show_the_file_read() {
+        let (details, stats) = fixture_details("amd-desktop", SegmentKind::Cpu);
+        let path = stats.cpu_sensor_path.clone().unwrap();
+
+        assert!(path.ends_with("sys/class/hwmon/hwmon2/temp1_input"), "{}", path);
+        assert!(Path::new(&path).is_file());
+        let lines: Vec<&str> = details.lines().collect();
+        assert_eq!(lines[1..], ["Temperature: 45.2°C", "Sensor: k10temp Tctl", &path]);
+    }
+
+    #[test]
+    fn thermal_zone_details_show_the_zone_read() {
+        let (details, stats) = fixture_details("intel-laptop", SegmentKind::Cpu);
+        let path = stats.cpu_sensor_path.unwrap();
+
+        assert!(path.ends_with("sys/class/thermal/thermal_zone1/temp"), "{}", path);
+        assert_eq!(details.lines().last(), Some(path.as_str()));
+    }
+
+    #[test]
+    fn gpu_details_list_each_card_with_its_file() {
+        let (details, stats) = fixture_details("amd-desktop", SegmentKind::Gpu);
+        let path = stats.gpus[0].path.clone().unwrap();
+
+        assert!(Path::new(&path).is_file());
+        assert_eq!(details.lines().collect::<Vec<_>>(), ["card1 (amdgpu): 52.0°C", &path]);
+    }
+
+    #[test]
+    fn hidden_monitors_leave_out_their_segments() {
+        let mut config = Config::default();
+        config.monitors.cpu_usage = false;
+        config.monitors.cpu_temperature = false;
+        config.monitors.network = false;
+        let stats = Snapshot {
+            gpu_celsius: Some(50.0),
+            ..Snapshot::default()
+        };
+
+        let segments = build(&stats, &config);
+        let kinds: Vec<SegmentKind> = segments.iter().map(|segment| segment.kind).collect();
+        assert_eq!(kinds, [SegmentKind::Gpu, SegmentKind::Memory]);
+    }
+}
@@ -0,0 +1,224 @@
+//! Settings page shown in the applet popup
+
+use cosmic::iced::Alignment;
+use cosmic::iced_widget::{Column, Row};
+use cosmic::widget::{settings, slider, text, text_input, toggler};
+use cosmic::Element;
+use std::collections::HashMap;
+
+use crate::config::validate::{Severity, REFRESH_INTERVAL_MIN_MS};
+use crate::config::{Config, Issue, Threshold};
+
+/// Slider range for the refresh interval, in milliseconds; the low end matches validation
+const REFRESH_INTERVAL_MIN: u32 = REFRESH_INTERVAL_MIN_MS as u32;
+const REFRESH_INTERVAL_MAX: u32 = 5000;
+const REFRESH_INTERVAL_STEP: u32 = 100;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Monitor(Monitor, bool),
+    RefreshInterval(u32),
+    Threshold(ThresholdField, Level, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Monitor {
+    CpuUsage,
+    CpuTemperature,
+    GpuTemperature,
+    Memory,
+    Network,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThresholdField {
+    CpuUsage,
+    CpuTemperature,
+    GpuTemperature,
+    MemoryUsage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Level {
+    Warning,
+    Critical,
+}
+
+/// Threshold text being typed, kept until it parses as a number
+#[derive(Debug, Default)]
+pub struct Drafts {
+    thresholds: HashMap<(ThresholdField, Level), String>,
+}
+
+impl Drafts {
+    pub fn clear(&mut self) {
+        self.thresholds.clear();
+    }
+}
+
+impl Monitor {
+    const ALL: [(Monitor, &'static str); 5] = [
+        (Monitor::CpuUsage, "CPU usage"),
+        (Monitor::CpuTemperature, "CPU temperature"),
+        (Monitor::GpuTemperature, "GPU temperature"),
+        (Monitor::Memory, "Memory"),
+        (Monitor::Network, "Network"),
+    ];
+
+    fn get(self, config: &Config) -> bool {
+        let toggles = &config.monitors;
+        match self {
+            Monitor::CpuUsage => toggles.cpu_usage,
+            Monitor::CpuTemperature => toggles.cpu_temperature,
+            Monitor::GpuTemperature => toggles.gpu_temperature,
+            Monitor::Memory => toggles.memory,
+            Monitor::Network => toggles.network,
+        }
+    }
+
+    fn get_mut(self, config: &mut Config) -> &mut bool {
+        let toggles = &mut config.monitors;
+        match self {
+            Monitor::CpuUsage => &mut toggles.cpu_usage,
+            Monitor::CpuTemperature => &mut toggles.cpu_temperature,
+            Monitor::GpuTemperature => &mut toggles.gpu_temperature,
+            Monitor::Memory => &mut toggles.memory,
+            Monitor::Network => &mut toggles.network,
+        }
+    }
+}
+
+impl ThresholdField {
+    const ALL: [(ThresholdField, &'static str); 4] = [
+        (ThresholdField::CpuUsage, "CPU usage (%)"),
+        (ThresholdField::CpuTemperature, "CPU temperature (°C)"),
+        (ThresholdField::GpuTemperature, "GPU temperature (°C)"),
+        (ThresholdField::MemoryUsage, "Memory usage (%)"),
+    ];
+
+    fn get(self, config: &Config) -> Threshold {
+        let thresholds = &config.thresholds;
+        match self {
+            ThresholdField::CpuUsage => thresholds.cpu_usage,
+            ThresholdField::CpuTemperature => thresholds.cpu_temperature,
+            ThresholdField::GpuTemperature => thresholds.gpu_temperature,
+            ThresholdField::MemoryUsage => thresholds.memory_usage,
+        }
+    }
+
+    fn get_mut(self, config: &mut Config) -> &mut Threshold {
+        let thresholds = &mut config.thresholds;
+        match self {
+            ThresholdField::CpuUsage => &mut thresholds.cpu_usage,
+            ThresholdField::CpuTemperature => &mut thresholds.cpu_temperature,
+            ThresholdField::GpuTemperature => &mut thresholds.gpu_temperature,
+            ThresholdField::MemoryUsage => &mut thresholds.memory_usage,
+        }
+    }
+}
+
+impl Level {
+    fn get(self, threshold: &Threshold) -> f32 {
+        match self {
+            Level::Warning => threshold.warning,
+            Level::Critical => threshold.critical,
+        }
+    }
+
+    fn get_mut(self, threshold: &mut Threshold) -> &mut f32 {
+        match self {
+            Level::Warning => &mut threshold.warning,
+            Level::Critical => &mut threshold.critical,
+        }
+    }
+}
+
+/// Apply a settings change, returning the updated config if anything changed
+pub fn update(config: &Config, drafts: &mut Drafts, message: Message) -> Option<Config> {
+    let mut config = config.clone();
+
+    match message {
+        Message::Monitor(monitor, enabled) => {
+            *monitor.get_mut(&mut config) = enabled;
+        }
+        Message::RefreshInterval(interval_ms) => {
+            config.refresh_interval_ms = u64::from(interval_ms);
+        }
+        Message::Threshold(field, level, input) => {
+            let parsed = input.trim().parse::<f32>().ok().filter(|value| value.is_finite());
+            drafts.thresholds.insert((field, level), input);
+
+            let threshold = field.get_mut(&mut config);
+            *level.get_mut(threshold) = parsed?;
+            // Keep the draft until the pair is ordered again
+            if threshold.warning > threshold.critical {
+                return None;
+            }
+        }
+    }
+
+    Some(config)
+}
+
+pub fn view<'a>(config: &'a Config, issues: &'a [Issue], drafts: &'a Drafts) -> Element<'a, Message> {
+    let mut monitors = settings::section().title("Monitors");
+    for (monitor, label) in Monitor::ALL {
+        monitors = monitors.add(settings::item(
+            label,
+            toggler(monitor.get(config)).on_toggle(move |enabled| Message::Monitor(monitor, enabled)),
+        ));
+    }
+
+    let interval_ms = (config.refresh_interval_ms as u32).clamp(REFRESH_INTERVAL_MIN, REFRESH_INTERVAL_MAX);
+    let refresh = settings::section().title("Refresh").add(settings::item(
+        format!("Every {} ms", config.refresh_interval_ms),
+        slider(REFRESH_INTERVAL_MIN..=REFRESH_INTERVAL_MAX, interval_ms, Message::RefreshInterval)
+            .step(REFRESH_INTERVAL_STEP),
+    ));
+
+    let mut thresholds = settings::section().title("Thresholds (warning / critical)");
+    for (field, label) in ThresholdField::ALL {
+        let threshold = field.get(config);
+        let input = |level: Level| {
+            let value = drafts
+                .thresholds
+                .get(&(field, level))
+                .cloned()
+                .unwrap_or_else(|| level.get(&threshold).to_string());
+
+            text_input("", value)
+                .on_input(move |input| Message::Threshold(field, level, input))
+                .width(64)
+        };
+
+        thresholds = thresholds.add(settings::item(
+            label,
+            Row::from_vec(vec![
+                input(Level::Warning).into(),
+                text("/").into(),
+                input(Level::Critical).into(),
+            ])
+            .spacing(4)
+            .align_y(Alignment::Center),
+        ));
+    }
+
+    let mut sections = vec![text::heading("System Stats").into()];
+    if !issues.is_empty() {
+        let mut problems = settings::section().title("Config problems");
+        for issue in issues {
+            let severity = match issue.severity {
+                Severity::Warning => "Warning",
+                Severity::Error => "Error",
+            };
+            problems = problems.add(settings::item(severity, text::body(issue.to_string())));
+        }
+        sections.push(problems.into());
+    }
+    sections.extend([monitors.into(), refresh.into(), thresholds.into()]);
+
+    Column::from_vec(sections)
+    .spacing(12)
+    .padding(12)
+    .into()
+}
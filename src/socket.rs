@@ -0,0 +1,203 @@
+//! Query socket for scripts
+//!
+//! A Unix socket at `$XDG_RUNTIME_DIR/systemstats.sock` speaking newline-
+//! delimited JSON. Each request line is answered with one response line,
+//! except `subscribe`, which streams every new sample until the client hangs
+//! up. Samples have the same fields as a recording.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::history::Shared;
+use crate::listener::AcceptLoop;
+use crate::recording::Record;
+
+const FILE_NAME: &str = "systemstats.sock";
+
+/// Samples a slow subscriber may fall behind by before new ones are skipped
+const SUBSCRIBER_BACKLOG: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    /// The latest sample
+    Snapshot,
+    /// Samples from the last `seconds`, or everything kept when left out
+    History {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seconds: Option<u64>,
+    },
+    /// Every sample from now on
+    Subscribe,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Snapshot(Record),
+    History { samples: Vec<Record> },
+    Error { message: String },
+}
+
+/// Default socket location
+pub fn path() -> Option<PathBuf> {
+    dirs::runtime_dir().map(|dir| dir.join(FILE_NAME))
+}
+
+type Senders = Mutex<Vec<SyncSender<Record>>>;
+type Subscribers = Arc<Senders>;
+
+/// A listening socket, removed when dropped
+pub struct Server {
+    path: PathBuf,
+    subscribers: Subscribers,
+    _accepting: AcceptLoop,
+}
+
+impl Server {
+    /// Listen at `path`, answering from `history`
+    ///
+    /// Fails with `AddrInUse` when another instance is already answering there.
+    pub fn start(path: &Path, history: Shared) -> io::Result<Self> {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is already being served", path.display()),
+            ));
+        }
+        // Left behind by an instance that didn't shut down cleanly
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        let subscribers = Subscribers::default();
+        // Subscriptions stay open, so every client gets its own thread
+        let accepting = AcceptLoop::spawn("socket", listener, {
+            // Weak, so no client can subscribe once the server is gone
+            let subscribers = Arc::downgrade(&subscribers);
+            move |stream| serve(stream, &history, &subscribers)
+        })?;
+
+        log::info!("Answering queries on {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            subscribers,
+            _accepting: accepting,
+        })
+    }
+
+    /// Send a new sample to every subscriber
+    pub fn publish(&self, record: &Record) {
+        let Ok(mut subscribers) = self.subscribers.lock() else {
+            return;
+        };
+        subscribers.retain(|subscriber| match subscriber.try_send(record.clone()) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        // Dropping the senders ends every subscription
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.clear();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Answer one client's requests until it hangs up
+fn serve(stream: UnixStream, history: &Shared, subscribers: &Weak<Senders>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str(&line) {
+            Ok(Request::Snapshot) => match history
+                .lock()
+                .ok()
+                .and_then(|history| history.latest().cloned())
+            {
+                Some(record) => Response::Snapshot(record),
+                None => Response::Error {
+                    message: "nothing sampled yet".to_string(),
+                },
+            },
+            Ok(Request::History { seconds }) => match history.lock() {
+                Ok(history) => Response::History {
+                    samples: history
+                        .since(seconds.unwrap_or(u64::MAX))
+                        .cloned()
+                        .collect(),
+                },
+                Err(_) => Response::Error {
+                    message: "history is unavailable".to_string(),
+                },
+            },
+            Ok(Request::Subscribe) => {
+                let (sender, receiver) = mpsc::sync_channel(SUBSCRIBER_BACKLOG);
+                // Shutting down: the sender is dropped and the stream ends right away
+                if let Some(subscribers) = subscribers.upgrade() {
+                    if let Ok(mut subscribers) = subscribers.lock() {
+                        subscribers.push(sender);
+                    }
+                }
+                return stream_samples(&mut writer, receiver);
+            }
+            Err(e) => Response::Error {
+                message: format!("invalid request: {}", e),
+            },
+        };
+
+        write_line(&mut writer, &response)?;
+    }
+
+    Ok(())
+}
+
+fn stream_samples(writer: &mut UnixStream, samples: Receiver<Record>) -> io::Result<()> {
+    for record in samples {
+        write_line(writer, &Response::Snapshot(record))?;
+    }
+    Ok(())
+}
+
+fn write_line(writer: &mut impl Write, value: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+/// Send one request and print every response line to stdout, for `--query`
+///
+/// Returns once the answer is complete, or when the server goes away for `subscribe`.
+pub fn query(path: &Path, request: &Request) -> io::Result<()> {
+    let mut stream = UnixStream::connect(path)?;
+    write_line(&mut stream, request)?;
+
+    let mut stdout = io::stdout().lock();
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        writeln!(stdout, "{}", line)?;
+        stdout.flush()?;
+
+        if let Ok(Response::Error { message }) = serde_json::from_str(&line) {
+            return Err(io::Error::other(message));
+        }
+        if *request != Request::Subscribe {
+            break;
+        }
+    }
+    Ok(())
+}
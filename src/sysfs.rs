@@ -0,0 +1,42 @@
+//! Where kernel interfaces are read from
+//!
+//! Readers resolve `/sys` and `/proc` paths through a [`Root`] instead of
+//! using them directly, so the applet can be pointed at a captured tree such
+//! as those in `tests/fixtures/` by setting `SYSTEMSTATS_ROOT`.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+const ROOT_VAR: &str = "SYSTEMSTATS_ROOT";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Root(PathBuf);
+
+impl Root {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self(path.into())
+    }
+
+    /// The live system, or the tree named by `SYSTEMSTATS_ROOT` when it's set
+    pub fn from_env() -> Self {
+        match env::var_os(ROOT_VAR).filter(|root| !root.is_empty()) {
+            Some(root) => {
+                let root = Self::new(root);
+                log::info!("Reading sysfs and procfs from {}", root.0.display());
+                root
+            }
+            None => Self::new("/"),
+        }
+    }
+
+    /// Whether this is the running system rather than a captured tree
+    pub fn is_live(&self) -> bool {
+        self.0 == Path::new("/")
+    }
+
+    /// Resolve an absolute system path such as `/sys/class/drm` under this root
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        let path = path.as_ref();
+        self.0.join(path.strip_prefix("/").unwrap_or(path))
+    }
+}
@@ -0,0 +1,625 @@
+//! Output format templates for the panel string
+//!
+//! A template is literal text with `{field}` or `{field:spec}` placeholders, e.g.
+//! `{cpu.usage} {cpu.temp} · {mem.used}/{mem.total} · ↓{net.rx}`. The spec is
+//! `[align][width][.precision][unit]`, where align is one of `<`, `>` or `^`.
+//! Literal braces are written as `{{` and `}}`.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::config::{Config, MemoryUnit, MonitorToggles, NetworkUnit, TemperatureUnit};
+use crate::formatting::*;
+use crate::monitors::Snapshot;
+use crate::segments::{widest_network_bps, WIDEST_TEMPERATURE_CELSIUS};
+
+/// Shown in place of a value whose monitor is disabled or whose sensor is missing
+const MISSING: &str = "--";
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Template {
+    source: String,
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Literal(String),
+    Field(FieldSpec),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FieldSpec {
+    field: Field,
+    align: Align,
+    width: usize,
+    precision: Option<usize>,
+    unit: Option<Unit>,
+}
+
+/// One rendered piece of a template, for laying the line out piece by piece
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    pub text: String,
+    /// For placeholders, the widest the value can render with the current config
+    pub widest: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    CpuUsage,
+    CpuTemp,
+    GpuTemp,
+    MemUsed,
+    MemTotal,
+    MemPercent,
+    NetRx,
+    NetTx,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Percent,
+    Temperature(TemperatureUnit),
+    /// Prefix magnitude, where 1 is kilo and 3 is giga
+    Memory(MemoryUnit, u32),
+    Network(NetworkUnit, usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateError {
+    /// Character offset into the template where the problem starts
+    pub column: usize,
+    pub kind: TemplateErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateErrorKind {
+    UnclosedPlaceholder,
+    UnmatchedClosingBrace,
+    EmptyPlaceholder,
+    UnknownField(String),
+    InvalidSpec(String),
+    UnknownUnit { field: String, unit: String },
+}
+
+impl Field {
+    const ALL: [(&'static str, Field); 8] = [
+        ("cpu.usage", Field::CpuUsage),
+        ("cpu.temp", Field::CpuTemp),
+        ("gpu.temp", Field::GpuTemp),
+        ("mem.used", Field::MemUsed),
+        ("mem.total", Field::MemTotal),
+        ("mem.percent", Field::MemPercent),
+        ("net.rx", Field::NetRx),
+        ("net.tx", Field::NetTx),
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(field_name, _)| *field_name == name)
+            .map(|(_, field)| *field)
+    }
+
+    fn units(self) -> &'static [(&'static str, Unit)] {
+        match self {
+            Field::CpuUsage | Field::MemPercent => &[("%", Unit::Percent)],
+            Field::CpuTemp | Field::GpuTemp => &[
+                ("C", Unit::Temperature(TemperatureUnit::Celsius)),
+                ("F", Unit::Temperature(TemperatureUnit::Fahrenheit)),
+                ("K", Unit::Temperature(TemperatureUnit::Kelvin)),
+            ],
+            Field::MemUsed | Field::MemTotal => &[
+                ("KB", Unit::Memory(MemoryUnit::Si, 1)),
+                ("MB", Unit::Memory(MemoryUnit::Si, 2)),
+                ("GB", Unit::Memory(MemoryUnit::Si, 3)),
+                ("KiB", Unit::Memory(MemoryUnit::Iec, 1)),
+                ("MiB", Unit::Memory(MemoryUnit::Iec, 2)),
+                ("GiB", Unit::Memory(MemoryUnit::Iec, 3)),
+            ],
+            Field::NetRx | Field::NetTx => &[
+                ("bps", Unit::Network(NetworkUnit::Bits, 0)),
+                ("Kbps", Unit::Network(NetworkUnit::Bits, 1)),
+                ("Mbps", Unit::Network(NetworkUnit::Bits, 2)),
+                ("Gbps", Unit::Network(NetworkUnit::Bits, 3)),
+                ("B/s", Unit::Network(NetworkUnit::Bytes, 0)),
+                ("KB/s", Unit::Network(NetworkUnit::Bytes, 1)),
+                ("MB/s", Unit::Network(NetworkUnit::Bytes, 2)),
+                ("GB/s", Unit::Network(NetworkUnit::Bytes, 3)),
+            ],
+        }
+    }
+
+    fn is_enabled(self, toggles: &MonitorToggles) -> bool {
+        match self {
+            Field::CpuUsage => toggles.cpu_usage,
+            Field::CpuTemp => toggles.cpu_temperature,
+            Field::GpuTemp => toggles.gpu_temperature,
+            Field::MemUsed | Field::MemTotal | Field::MemPercent => toggles.memory,
+            Field::NetRx | Field::NetTx => toggles.network,
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = source.chars().enumerate().peekable();
+
+        while let Some((column, c)) = chars.next() {
+            match c {
+                '{' if chars.peek().is_some_and(|&(_, next)| next == '{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek().is_some_and(|&(_, next)| next == '}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => {
+                    return Err(TemplateError {
+                        column,
+                        kind: TemplateErrorKind::UnmatchedClosingBrace,
+                    });
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    let mut closed = false;
+                    for (_, c) in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        placeholder.push(c);
+                    }
+
+                    if !closed {
+                        return Err(TemplateError {
+                            column,
+                            kind: TemplateErrorKind::UnclosedPlaceholder,
+                        });
+                    }
+
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    let spec = FieldSpec::parse(&placeholder)
+                        .map_err(|kind| TemplateError { column, kind })?;
+                    pieces.push(Piece::Field(spec));
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            pieces,
+        })
+    }
+
+    pub fn render(&self, stats: &Snapshot, config: &Config) -> String {
+        let mut output = String::new();
+
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(text) => output.push_str(text),
+                Piece::Field(spec) => output.push_str(&spec.render(stats, config)),
+            }
+        }
+
+        output
+    }
+
+    /// Rendered pieces in order, each placeholder with room to reserve so the line doesn't jitter
+    pub fn parts(&self, stats: &Snapshot, config: &Config) -> Vec<Part> {
+        let widest = widest_stats(stats, config);
+
+        self.pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Literal(text) => Part {
+                    text: text.clone(),
+                    widest: None,
+                },
+                Piece::Field(spec) => Part {
+                    text: spec.render(stats, config),
+                    widest: Some(spec.render(&widest, config)),
+                },
+            })
+            .collect()
+    }
+}
+
+/// `stats` with every value at the widest it's expected to reach; missing sensors stay missing
+fn widest_stats(stats: &Snapshot, config: &Config) -> Snapshot {
+    let widest_celsius = |celsius: Option<f32>| celsius.map(|_| WIDEST_TEMPERATURE_CELSIUS);
+    let widest_bps = widest_network_bps(config.units.network);
+
+    Snapshot {
+        cpu_usage: 100.0,
+        cpu_celsius: widest_celsius(stats.cpu_celsius),
+        gpu_celsius: widest_celsius(stats.gpu_celsius),
+        memory_used_bytes: stats.memory_total_bytes,
+        download_bps: widest_bps,
+        upload_bps: widest_bps,
+        ..stats.clone()
+    }
+}
+
+impl FieldSpec {
+    fn parse(placeholder: &str) -> Result<Self, TemplateErrorKind> {
+        let (name, spec) = match placeholder.split_once(':') {
+            Some((name, spec)) => (name.trim(), spec),
+            None => (placeholder.trim(), ""),
+        };
+
+        if name.is_empty() {
+            return Err(TemplateErrorKind::EmptyPlaceholder);
+        }
+
+        let field = Field::from_name(name)
+            .ok_or_else(|| TemplateErrorKind::UnknownField(name.to_string()))?;
+
+        let mut rest = spec;
+
+        let align = match rest.chars().next() {
+            Some('<') => Align::Left,
+            Some('>') => Align::Right,
+            Some('^') => Align::Center,
+            _ => Align::Right,
+        };
+        if rest.starts_with(['<', '>', '^']) {
+            rest = &rest[1..];
+        }
+
+        let width_len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let width = if width_len > 0 {
+            rest[..width_len]
+                .parse()
+                .map_err(|_| TemplateErrorKind::InvalidSpec(spec.to_string()))?
+        } else {
+            0
+        };
+        rest = &rest[width_len..];
+
+        let mut precision = None;
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let precision_len = after_dot
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(after_dot.len());
+            if precision_len == 0 {
+                return Err(TemplateErrorKind::InvalidSpec(spec.to_string()));
+            }
+            precision = Some(
+                after_dot[..precision_len]
+                    .parse()
+                    .map_err(|_| TemplateErrorKind::InvalidSpec(spec.to_string()))?,
+            );
+            rest = &after_dot[precision_len..];
+        }
+
+        let unit = if rest.is_empty() {
+            None
+        } else {
+            let unit = field
+                .units()
+                .iter()
+                .find(|(unit_name, _)| *unit_name == rest)
+                .map(|(_, unit)| *unit)
+                .ok_or_else(|| TemplateErrorKind::UnknownUnit {
+                    field: name.to_string(),
+                    unit: rest.to_string(),
+                })?;
+            Some(unit)
+        };
+
+        Ok(Self {
+            field,
+            align,
+            width,
+            precision,
+            unit,
+        })
+    }
+
+    fn render(&self, stats: &Snapshot, config: &Config) -> String {
+        let value = if self.field.is_enabled(&config.monitors) {
+            self.format_value(stats, config)
+        } else {
+            None
+        };
+        let value = value.unwrap_or_else(|| MISSING.to_string());
+        let width = self.width;
+
+        match self.align {
+            Align::Left => format!("{value:<width$}"),
+            Align::Right => format!("{value:>width$}"),
+            Align::Center => format!("{value:^width$}"),
+        }
+    }
+
+    fn format_value(&self, stats: &Snapshot, config: &Config) -> Option<String> {
+        let formatted = match self.field {
+            Field::CpuUsage => self.percentage(stats.cpu_usage, config),
+            Field::MemPercent => self.percentage(stats.memory_usage_percent(), config),
+            Field::CpuTemp => self.temperature(stats.cpu_celsius?, config),
+            Field::GpuTemp => self.temperature(stats.gpu_celsius?, config),
+            Field::MemUsed => self.memory(stats.memory_used_bytes, config),
+            Field::MemTotal => self.memory(stats.memory_total_bytes, config),
+            Field::NetRx => self.network(stats.download_bps, config),
+            Field::NetTx => self.network(stats.upload_bps, config),
+        };
+        Some(formatted)
+    }
+
+    fn percentage(&self, value: f32, config: &Config) -> String {
+        match self.precision {
+            Some(precision) => format!("{:.precision$}%", value.clamp(0.0, 100.0)),
+            None => format_percentage(value, &config.units),
+        }
+    }
+
+    fn temperature(&self, celsius: f32, config: &Config) -> String {
+        let unit = match self.unit {
+            Some(Unit::Temperature(unit)) => unit,
+            _ if self.precision.is_none() => return format_temperature(celsius, &config.units),
+            _ => config.units.temperature,
+        };
+        let (value, label) = convert_temperature(celsius, unit);
+        format!("{:.*}{}", self.precision.unwrap_or(0), value, label)
+    }
+
+    fn memory(&self, bytes: u64, config: &Config) -> String {
+        let (unit, magnitude) = match self.unit {
+            Some(Unit::Memory(unit, magnitude)) => (unit, magnitude),
+            _ if self.precision.is_none() => return format_memory(bytes, &config.units),
+            _ => (config.units.memory, 3),
+        };
+        let (divisor, label) = memory_scale(unit, magnitude);
+        format!("{:.*} {}", self.precision.unwrap_or(1), bytes as f64 / divisor, label)
+    }
+
+    fn network(&self, bytes_per_sec: u64, config: &Config) -> String {
+        let (value, unit, magnitude) = match self.unit {
+            Some(Unit::Network(unit, magnitude)) => {
+                (network_fixed_scale(bytes_per_sec, unit, magnitude), unit, magnitude)
+            }
+            _ if self.precision.is_none() => {
+                return format_network_speed(bytes_per_sec, &config.units);
+            }
+            _ => {
+                let (value, magnitude) = network_auto_scale(bytes_per_sec, config.units.network);
+                (value, config.units.network, magnitude)
+            }
+        };
+        let precision = self.precision.unwrap_or_else(|| network_precision(magnitude));
+        format!("{:.*} {}", precision, value, network_label(unit, magnitude))
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = TemplateError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Self::parse(&source)
+    }
+}
+
+impl From<Template> for String {
+    fn from(template: Template) -> Self {
+        template.source
+    }
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let column = self.column + 1;
+        match &self.kind {
+            TemplateErrorKind::UnclosedPlaceholder => {
+                write!(f, "unclosed `{{` at column {column}")
+            }
+            TemplateErrorKind::UnmatchedClosingBrace => {
+                write!(f, "unmatched `}}` at column {column} (write `}}}}` for a literal brace)")
+            }
+            TemplateErrorKind::EmptyPlaceholder => {
+                write!(f, "empty placeholder at column {column}")
+            }
+            TemplateErrorKind::UnknownField(name) => {
+                let known: Vec<&str> = Field::ALL.iter().map(|(name, _)| *name).collect();
+                write!(
+                    f,
+                    "unknown field `{name}` at column {column} (expected one of: {})",
+                    known.join(", ")
+                )
+            }
+            TemplateErrorKind::InvalidSpec(spec) => {
+                write!(
+                    f,
+                    "invalid format spec `{spec}` at column {column} (expected [align][width][.precision][unit])"
+                )
+            }
+            TemplateErrorKind::UnknownUnit { field, unit } => {
+                let known: Vec<&str> = Field::from_name(field)
+                    .map(|f| f.units().iter().map(|(name, _)| *name).collect())
+                    .unwrap_or_default();
+                write!(
+                    f,
+                    "unknown unit `{unit}` for `{field}` at column {column} (expected one of: {})",
+                    known.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn stats() -> Snapshot {
+        Snapshot {
+            cpu_usage: 42.4,
+            cpu_celsius: Some(55.6),
+            gpu_celsius: Some(61.2),
+            memory_used_bytes: 4 * GIB,
+            memory_total_bytes: 16 * GIB,
+            download_bps: 1_300_000,
+            upload_bps: 125_000,
+            ..Snapshot::default()
+        }
+    }
+
+    fn render(source: &str) -> String {
+        Template::parse(source).unwrap().render(&stats(), &Config::default())
+    }
+
+    fn error(source: &str) -> TemplateError {
+        Template::parse(source).unwrap_err()
+    }
+
+    #[test]
+    fn renders_every_field_with_configured_units() {
+        assert_eq!(render("{cpu.usage}"), "42%");
+        assert_eq!(render("{cpu.temp}"), "56°C");
+        assert_eq!(render("{gpu.temp}"), "61°C");
+        assert_eq!(render("{mem.used}"), "4.0 GiB");
+        assert_eq!(render("{mem.total}"), "16.0 GiB");
+        assert_eq!(render("{mem.percent}"), "25%");
+        assert_eq!(render("{net.rx}"), "10.4 Mbps");
+        assert_eq!(render("{net.tx}"), "1.0 Mbps");
+    }
+
+    #[test]
+    fn keeps_literal_text_around_fields() {
+        assert_eq!(
+            render("CPU {cpu.usage} · {mem.used}/{mem.total}"),
+            "CPU 42% · 4.0 GiB/16.0 GiB"
+        );
+    }
+
+    #[test]
+    fn applies_precision_and_unit() {
+        assert_eq!(render("{cpu.usage:.1}"), "42.4%");
+        assert_eq!(render("{cpu.temp:.1F}"), "132.1°F");
+        assert_eq!(render("{cpu.temp:K}"), "329 K");
+        assert_eq!(render("{mem.used:.0GB}"), "4 GB");
+        assert_eq!(render("{mem.used:MiB}"), "4096.0 MiB");
+        assert_eq!(render("{net.rx:MB/s}"), "1.3 MB/s");
+        assert_eq!(render("{net.tx:.2Kbps}"), "1000.00 Kbps");
+    }
+
+    #[test]
+    fn pads_to_width_with_alignment() {
+        assert_eq!(render("[{cpu.usage:5}]"), "[  42%]");
+        assert_eq!(render("[{cpu.usage:>5}]"), "[  42%]");
+        assert_eq!(render("[{cpu.usage:<5}]"), "[42%  ]");
+        assert_eq!(render("[{cpu.usage:^7}]"), "[  42%  ]");
+    }
+
+    #[test]
+    fn missing_or_disabled_values_render_as_dashes() {
+        let template = Template::parse("{cpu.temp} {net.rx}").unwrap();
+        let mut config = Config::default();
+        config.monitors.network = false;
+        let stats = Snapshot {
+            cpu_celsius: None,
+            ..stats()
+        };
+
+        assert_eq!(template.render(&stats, &config), "-- --");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(render("{{cpu.usage}}"), "{cpu.usage}");
+        assert_eq!(render("{{{cpu.usage}}}"), "{42%}");
+    }
+
+    #[test]
+    fn rejects_unterminated_placeholder() {
+        let error = error("CPU {cpu.usage");
+        assert_eq!(error.kind, TemplateErrorKind::UnclosedPlaceholder);
+        assert_eq!(error.column, 4);
+    }
+
+    #[test]
+    fn rejects_lone_closing_brace() {
+        let error = error("CPU }");
+        assert_eq!(error.kind, TemplateErrorKind::UnmatchedClosingBrace);
+        assert_eq!(error.column, 4);
+    }
+
+    #[test]
+    fn rejects_empty_placeholder() {
+        assert_eq!(error("{}").kind, TemplateErrorKind::EmptyPlaceholder);
+        assert_eq!(error("{ :5}").kind, TemplateErrorKind::EmptyPlaceholder);
+    }
+
+    #[test]
+    fn rejects_unknown_placeholder() {
+        let error = error("ok {disk.free}");
+        assert_eq!(error.kind, TemplateErrorKind::UnknownField("disk.free".to_string()));
+        assert_eq!(error.column, 3);
+        assert!(error.to_string().contains("expected one of: cpu.usage"));
+    }
+
+    #[test]
+    fn rejects_malformed_spec() {
+        assert_eq!(
+            error("{cpu.usage:5.}").kind,
+            TemplateErrorKind::InvalidSpec("5.".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_unit_of_another_field() {
+        assert_eq!(
+            error("{cpu.temp:GB}").kind,
+            TemplateErrorKind::UnknownUnit {
+                field: "cpu.temp".to_string(),
+                unit: "GB".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn parts_reserve_each_placeholders_widest_value() {
+        let template = Template::parse("CPU {cpu.usage} {cpu.temp} {net.rx}").unwrap();
+        let parts = template.parts(&stats(), &Config::default());
+
+        let texts: Vec<&str> = parts.iter().map(|part| part.text.as_str()).collect();
+        assert_eq!(texts, ["CPU ", "42%", " ", "56°C", " ", "10.4 Mbps"]);
+
+        let widest: Vec<Option<&str>> = parts.iter().map(|part| part.widest.as_deref()).collect();
+        assert_eq!(
+            widest,
+            [None, Some("100%"), None, Some("100°C"), None, Some("999.9 Mbps")]
+        );
+    }
+
+    #[test]
+    fn serializes_as_its_source() {
+        let source = "{cpu.usage:>4} {{x}}";
+        let template = Template::parse(source).unwrap();
+        assert_eq!(String::from(template.clone()), source);
+        assert_eq!(Template::try_from(source.to_string()), Ok(template));
+    }
+}
@@ -0,0 +1,35 @@
+//! A private D-Bus daemon for tests of code that talks on the session bus
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+
+pub struct TestBus {
+    pub address: String,
+    daemon: Child,
+}
+
+impl TestBus {
+    /// Start a daemon, or `None` when `dbus-daemon` isn't installed and the test should be skipped
+    pub fn start() -> Option<Self> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .inspect_err(|e| eprintln!("Skipping: dbus-daemon unavailable ({})", e))
+            .ok()?;
+
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+        Some(Self {
+            address: address.trim().to_string(),
+            daemon,
+        })
+    }
+}
+
+impl Drop for TestBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
@@ -0,0 +1,23 @@
+//! Threshold states used to colour segments
+
+use crate::config::Threshold;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThresholdState {
+    #[default]
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl ThresholdState {
+    pub fn of(value: f32, threshold: &Threshold) -> Self {
+        if value >= threshold.critical {
+            ThresholdState::Critical
+        } else if value >= threshold.warning {
+            ThresholdState::Warning
+        } else {
+            ThresholdState::Normal
+        }
+    }
+}
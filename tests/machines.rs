@@ -0,0 +1,187 @@
+//! Every monitor against the captured machines in `tests/fixtures/`
+
+use cosmic_applet_systemstats::adaptive::power;
+use cosmic_applet_systemstats::config::Config;
+use cosmic_applet_systemstats::monitors::{GpuReading, MonitorStats, Snapshot};
+use cosmic_applet_systemstats::sysfs::Root;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const KIB: u64 = 1024;
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+/// A first round of readings from a fixture, as the collector takes on startup
+fn sample(name: &str) -> Snapshot {
+    let config = Config::default();
+    let mut monitors = MonitorStats::new(&config, &Root::new(fixture(name)));
+    assert!(monitors.update(&config, Instant::now()));
+    monitors.snapshot()
+}
+
+fn gpu(gpu: &str, device: &str, celsius: f32, path: Option<PathBuf>) -> GpuReading {
+    GpuReading {
+        gpu: gpu.into(),
+        device: device.into(),
+        celsius,
+        path: path.map(|path| path.display().to_string()),
+    }
+}
+
+#[test]
+fn amd_desktop() {
+    let root = fixture("amd-desktop");
+    let stats = sample("amd-desktop");
+
+    assert_eq!(stats.cpu_celsius, Some(45.25));
+    assert_eq!(stats.cpu_sensor.as_deref(), Some("k10temp Tctl"));
+    let cpu_path = root.join("sys/class/hwmon/hwmon2/temp1_input");
+    assert_eq!(stats.cpu_sensor_path, Some(cpu_path.display().to_string()));
+
+    // Junction and memory temperatures are ignored
+    let edge = root.join("sys/class/drm/card1/device/hwmon/hwmon3/temp1_input");
+    assert_eq!(stats.gpus, [gpu("card1", "amdgpu", 52.0, Some(edge))]);
+    assert_eq!(stats.gpu_celsius, Some(52.0));
+
+    assert_eq!(stats.memory_total_bytes, 32849612 * KIB);
+    assert_eq!(stats.memory_used_bytes, (32849612 - 24637208) * KIB);
+
+    assert_eq!(stats.interface.as_deref(), Some("enp6s0"));
+    assert_eq!(stats.received_bytes, 8421773190);
+    assert_eq!(stats.transmitted_bytes, 612334871);
+    // Rates need a second reading
+    assert_eq!((stats.download_bps, stats.upload_bps), (0, 0));
+
+    assert!(!power::on_battery(&Root::new(root)));
+}
+
+#[test]
+fn amd_laptop_charging() {
+    let root = fixture("amd-laptop-charging");
+    let stats = sample("amd-laptop-charging");
+
+    // acpitz comes first but isn't a CPU sensor
+    assert_eq!(stats.cpu_celsius, Some(58.5));
+    assert_eq!(stats.cpu_sensor.as_deref(), Some("k10temp Tctl"));
+
+    let edge = root.join("sys/class/drm/card0/device/hwmon/hwmon5/temp1_input");
+    assert_eq!(stats.gpus, [gpu("card0", "amdgpu", 47.0, Some(edge))]);
+
+    assert_eq!(stats.memory_total_bytes, 15647232 * KIB);
+    assert_eq!(stats.memory_used_bytes, (15647232 - 7823616) * KIB);
+    assert_eq!(stats.interface.as_deref(), Some("wlp1s0"));
+    assert_eq!(stats.received_bytes, 1523774112);
+
+    assert!(!power::on_battery(&Root::new(root)));
+}
+
+#[test]
+fn intel_laptop() {
+    let root = fixture("intel-laptop");
+    let stats = sample("intel-laptop");
+
+    // No coretemp, so the package thermal zone stands in
+    assert_eq!(stats.cpu_celsius, Some(62.0));
+    assert_eq!(stats.cpu_sensor.as_deref(), Some("x86_pkg_temp"));
+    let zone = root.join("sys/class/thermal/thermal_zone1/temp");
+    assert_eq!(stats.cpu_sensor_path, Some(zone.display().to_string()));
+
+    // i915 exposes no temperature
+    assert_eq!(stats.gpus, []);
+    assert_eq!(stats.gpu_celsius, None);
+
+    assert_eq!(stats.memory_total_bytes, 7990432 * KIB);
+    // The idle wireless card is skipped for the one with traffic
+    assert_eq!(stats.interface.as_deref(), Some("enx00e04c"));
+    assert_eq!(stats.transmitted_bytes, 40112334);
+
+    // Discharging, and the mouse battery doesn't count either way
+    assert!(power::on_battery(&Root::new(root)));
+}
+
+#[test]
+fn nvidia_desktop() {
+    let root = fixture("nvidia-desktop");
+    let stats = sample("nvidia-desktop");
+
+    assert_eq!(stats.cpu_celsius, Some(48.0));
+    assert_eq!(stats.cpu_sensor.as_deref(), Some("coretemp Package id 0"));
+    let package = root.join("sys/class/hwmon/hwmon1/temp1_input");
+    assert_eq!(stats.cpu_sensor_path, Some(package.display().to_string()));
+
+    // Read through the fixture's stand-in nvidia-smi
+    assert_eq!(stats.gpus, [gpu("nvidia0", "NVIDIA GeForce RTX 3070", 61.0, None)]);
+
+    assert_eq!(stats.memory_total_bytes, 65799168 * KIB);
+    assert_eq!(stats.interface.as_deref(), Some("eno1"));
+    assert_eq!(stats.received_bytes, 22017334455);
+
+    assert!(!power::on_battery(&Root::new(root)));
+}
+
+#[test]
+fn rates_come_from_the_change_between_readings() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = Root::new(dir.path());
+    let write = |path: &str, contents: &str| {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    };
+    let net_dev = |received: u64, transmitted: u64| {
+        format!(
+            "header\nheader\n  eth0: {} 0 0 0 0 0 0 0 {} 0 0 0 0 0 0 0\n",
+            received, transmitted
+        )
+    };
+
+    write("/proc/stat", "cpu  100 0 100 800 0 0 0 0 0 0\n");
+    write("/proc/net/dev", &net_dev(1_000, 500));
+    let config = Config::default();
+    let mut monitors = MonitorStats::new(&config, &root);
+
+    // Half busy since the first reading
+    write("/proc/stat", "cpu  150 0 150 900 0 0 0 0 0 0\n");
+    write("/proc/net/dev", &net_dev(1_000_000, 500));
+    monitors.update(&config, Instant::now());
+    let stats = monitors.snapshot();
+
+    assert_eq!(stats.cpu_usage, 50.0);
+    assert!(stats.download_bps > 0);
+    assert_eq!(stats.upload_bps, 0);
+    assert_eq!(stats.received_bytes, 1_000_000);
+}
+
+#[test]
+fn only_enabled_monitors_are_read() {
+    let mut config = Config::default();
+    config.monitors.network = false;
+    config.monitors.cpu_temperature = false;
+    config.monitors.gpu_temperature = false;
+    let mut monitors = MonitorStats::new(&config, &Root::new(fixture("amd-desktop")));
+    assert!(monitors.network.is_none() && monitors.temperature.is_none() && monitors.disk.is_none());
+
+    monitors.update(&config, Instant::now());
+    let stats = monitors.snapshot();
+    assert_eq!(stats.memory_total_bytes, 32849612 * KIB);
+    assert_eq!(stats.interface, None);
+    assert_eq!(stats.cpu_celsius, None);
+
+    // Switching them on starts their readers, switching memory off drops its one
+    let old = config.clone();
+    config.monitors.network = true;
+    config.monitors.cpu_temperature = true;
+    config.monitors.memory = false;
+    monitors.reconfigure(&old, &config);
+    assert!(monitors.network.is_some() && monitors.temperature.is_some());
+    assert!(monitors.memory.is_none());
+
+    monitors.update(&config, Instant::now());
+    let stats = monitors.snapshot();
+    assert_eq!(stats.interface.as_deref(), Some("enp6s0"));
+    assert_eq!(stats.cpu_celsius, Some(45.25));
+    assert_eq!(stats.memory_total_bytes, 0);
+}
@@ -0,0 +1,157 @@
+//! The query socket, spoken to the way scripts do
+
+use cosmic_applet_systemstats::history::Shared;
+use cosmic_applet_systemstats::monitors::Snapshot;
+use cosmic_applet_systemstats::recording::Record;
+use cosmic_applet_systemstats::socket::{Request, Response, Server};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn record(seconds: u64, cpu_usage: f32) -> Record {
+    let snapshot = Snapshot {
+        cpu_usage,
+        ..Snapshot::default()
+    };
+    Record::new(snapshot, UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// A history holding samples taken at `seconds`
+fn history(seconds: &[u64]) -> Shared {
+    let history = Shared::default();
+    for &at in seconds {
+        history.lock().unwrap().push(record(at, at as f32));
+    }
+    history
+}
+
+struct Client {
+    writer: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl Client {
+    fn connect(path: &Path) -> Self {
+        let writer = UnixStream::connect(path).unwrap();
+        writer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let reader = BufReader::new(writer.try_clone().unwrap());
+        Self { writer, reader }
+    }
+
+    fn send(&mut self, line: &str) {
+        writeln!(self.writer, "{}", line).unwrap();
+    }
+
+    fn request(&mut self, request: &Request) -> Response {
+        self.send(&serde_json::to_string(request).unwrap());
+        self.receive().unwrap()
+    }
+
+    /// The next response, `None` once the server closes the connection
+    fn receive(&mut self) -> Option<Response> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line).unwrap() {
+            0 => None,
+            _ => Some(serde_json::from_str(&line).unwrap()),
+        }
+    }
+}
+
+fn times(samples: &[Record]) -> Vec<u64> {
+    samples.iter().map(|record| record.time_ms / 1000).collect()
+}
+
+#[test]
+fn snapshot_answers_with_the_latest_sample() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("systemstats.sock");
+    let history = Shared::default();
+    let _server = Server::start(&path, history.clone()).unwrap();
+    let mut client = Client::connect(&path);
+
+    assert!(matches!(client.request(&Request::Snapshot), Response::Error { .. }));
+
+    history.lock().unwrap().push(record(10, 42.0));
+    assert_eq!(client.request(&Request::Snapshot), Response::Snapshot(record(10, 42.0)));
+}
+
+#[test]
+fn history_answers_with_the_requested_seconds() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("systemstats.sock");
+    let _server = Server::start(&path, history(&[100, 150, 160, 170])).unwrap();
+    let mut client = Client::connect(&path);
+
+    let request = Request::History { seconds: Some(20) };
+    let Response::History { samples } = client.request(&request) else {
+        panic!("expected history");
+    };
+    assert_eq!(times(&samples), [150, 160, 170]);
+
+    // Written by hand, the way a script would
+    client.send(r#"{"request": "history"}"#);
+    let Some(Response::History { samples }) = client.receive() else {
+        panic!("expected history");
+    };
+    assert_eq!(times(&samples), [100, 150, 160, 170]);
+
+    client.send(r#"{"request": "everything"}"#);
+    assert!(matches!(client.receive(), Some(Response::Error { .. })));
+}
+
+#[test]
+fn subscribe_streams_until_the_server_stops() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("systemstats.sock");
+    let server = Server::start(&path, Shared::default()).unwrap();
+    let mut client = Client::connect(&path);
+    client.send(&serde_json::to_string(&Request::Subscribe).unwrap());
+
+    // The client's thread registers the subscription, so publish until it shows up
+    let first = loop {
+        server.publish(&record(1, 1.0));
+        client.reader.get_ref().set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        let mut line = String::new();
+        match client.reader.read_line(&mut line) {
+            Ok(_) => break serde_json::from_str::<Response>(&line).unwrap(),
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+            Err(e) => panic!("{}", e),
+        }
+    };
+    assert_eq!(first, Response::Snapshot(record(1, 1.0)));
+    client.reader.get_ref().set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    // Drain whatever was published while waiting
+    server.publish(&record(2, 2.0));
+    while client.receive() != Some(Response::Snapshot(record(2, 2.0))) {}
+
+    drop(server);
+    assert_eq!(client.receive(), None);
+    assert!(!path.exists());
+}
+
+#[test]
+fn second_server_on_the_same_path_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("systemstats.sock");
+    let _server = Server::start(&path, Shared::default()).unwrap();
+
+    let second = Server::start(&path, Shared::default());
+    assert_eq!(second.err().map(|e| e.kind()), Some(io::ErrorKind::AddrInUse));
+
+    // The first one still answers
+    assert!(matches!(Client::connect(&path).request(&Request::Snapshot), Response::Error { .. }));
+}
+
+#[test]
+fn stale_socket_file_is_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("systemstats.sock");
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    let _server = Server::start(&path, history(&[5])).unwrap();
+    let mut client = Client::connect(&path);
+    assert_eq!(client.request(&Request::Snapshot), Response::Snapshot(record(5, 5.0)));
+}